@@ -14,15 +14,20 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result, anyhow};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use itertools::Itertools;
 use regex::Regex;
-use std::collections::{BTreeSet, HashMap, HashSet};
-use std::io::{Write, stdout};
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::{CString, OsStr, OsString};
+use std::io::{self, Write, stdout};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::{env, fs};
 
+mod report;
 #[cfg(test)]
 mod tests;
 
@@ -34,6 +39,8 @@ struct Cli {
     env: String,
 
     /// Filter non-directories from path
+    ///
+    /// Same as --pipeline exists,dedupe
     #[arg(short, long, default_value_t = false)]
     filter: bool,
 
@@ -42,285 +49,1920 @@ struct Cli {
     pretty: bool,
 
     /// Normalize directory names in path
+    ///
+    /// Same as --pipeline canonicalize,dedupe
     #[arg(short, long, default_value_t = false)]
     normalize: bool,
 
+    /// Clean directory names in path without resolving symlinks
+    ///
+    /// Same as --pipeline clean,dedupe
+    #[arg(short, long, default_value_t = false)]
+    clean: bool,
+
+    /// Filter stages to apply to the path, in order
+    ///
+    /// Each stage receives the path produced by the one before it.
+    /// When --clean, --filter or --normalize are also given their
+    /// stages run first, in that order, followed by dedupe and then
+    /// the stages listed here.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pipeline: Vec<Stage>,
+
+    /// What to do with entries whose metadata cannot be read while canonicalizing
+    #[arg(long, value_enum, default_value_t = OnError::Keep)]
+    on_error: OnError,
+
+    /// Fail if the environment variable is unset or not valid Unicode
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+
+    /// Value to use when the environment variable is unset
+    #[arg(long, value_name = "VALUE")]
+    default: Option<OsString>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+impl Cli {
+    /// Returns the filter stages requested by all options in the order they run.
+    fn stages(&self) -> Vec<Stage> {
+        let mut stages = Vec::new();
+        if self.clean {
+            stages.push(Stage::Clean);
+        }
+        if self.filter {
+            stages.push(Stage::Exists);
+        }
+        if self.normalize {
+            stages.push(Stage::Canonicalize);
+        }
+        if !stages.is_empty() {
+            stages.push(Stage::Dedupe);
+        }
+        stages.extend(&self.pipeline);
+        stages
+    }
+
+    /// Reads the path variable, falling back to --default when it is unset.
+    /// With --strict an unset variable without a default or a value that is
+    /// not valid Unicode is an error.
+    fn read_path(&self) -> Result<(OsString, PathSource)> {
+        let value = env::var_os(&self.env);
+        if self.strict
+            && let Some(value) = &value
+            && value.to_str().is_none()
+        {
+            return Err(anyhow!("{} is not valid Unicode", self.env));
+        }
+        match (value, &self.default) {
+            (Some(value), _) if value.is_empty() => Ok((value, PathSource::Empty)),
+            (Some(value), _) => Ok((value, PathSource::Set)),
+            (None, Some(default)) => Ok((default.clone(), PathSource::Default)),
+            (None, None) if self.strict => Err(anyhow!("{} is not set", self.env)),
+            (None, None) => Ok((OsString::new(), PathSource::Unset)),
+        }
+    }
+}
+
+/// Where the value of the path variable came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum PathSource {
+    /// The variable is set to a non-empty value
+    #[default]
+    Set,
+    /// The variable is set to an empty string
+    Empty,
+    /// The variable is not set
+    Unset,
+    /// The variable is not set and the --default value is used
+    Default,
+}
+
+impl PathSource {
+    /// Describes the source when it is anything other than a set variable.
+    fn describe(self, env: &str) -> Option<String> {
+        match self {
+            PathSource::Set => None,
+            PathSource::Empty => Some(format!("{env} is set but empty")),
+            PathSource::Unset => Some(format!("{env} is not set")),
+            PathSource::Default => Some(format!("{env} is not set, using the --default value")),
+        }
+    }
+}
+
+/// Transformations that can be applied to a path before printing it.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    /// Clean names without resolving symlinks
+    Clean,
+    /// Replace names with their canonical directory, dropping missing ones
+    Canonicalize,
+    /// Drop entries that are not existing directories
+    Exists,
+    /// Drop repeated entries, keeping the first
+    Dedupe,
+}
+
+impl Stage {
+    fn apply(self, path: Vec<OsString>, on_error: OnError) -> Result<Vec<OsString>> {
+        let mut result = Vec::new();
+        for dir in path {
+            let outcome = match self {
+                Stage::Clean => Ok(Some(clean_path(&dir))),
+                Stage::Canonicalize => canonicalize(&dir),
+                Stage::Exists => Ok(is_valid(&dir).unwrap_or(false).then(|| dir.clone())),
+                Stage::Dedupe => Ok(Some(dir.clone())),
+            };
+            match outcome {
+                Ok(Some(dir)) => result.push(dir),
+                Ok(None) => (),
+                Err(e) => {
+                    if let Some(dir) = on_error.handle(dir, e)? {
+                        result.push(dir);
+                    }
+                }
+            }
+        }
+        if self == Stage::Dedupe {
+            result = result.into_iter().unique().collect();
+        }
+        Ok(result)
+    }
+}
+
+/// How the canonicalize stage treats entries whose metadata cannot be read.
+/// The exists stage always drops them since they are not known directories.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum OnError {
+    /// Keep the entry unchanged and print a warning
+    #[default]
+    Keep,
+    /// Remove the entry and print a warning
+    Drop,
+    /// Stop with an error
+    Fail,
+}
+
+impl OnError {
+    /// Returns the entry to keep, if any, or the error when failing.
+    fn handle(self, dir: OsString, error: anyhow::Error) -> Result<Option<OsString>> {
+        match self {
+            OnError::Keep => {
+                eprintln!("warning: keeping {}: {error:#}", dir.display());
+                Ok(Some(dir))
+            }
+            OnError::Drop => {
+                eprintln!("warning: dropping {}: {error:#}", dir.display());
+                Ok(None)
+            }
+            OnError::Fail => Err(error.context(format!("unable to filter {}", dir.display()))),
+        }
+    }
+}
+
 #[derive(Subcommand, Debug, PartialEq, Default, Clone)]
 enum Commands {
     /// Print the current PATH one directory per line
     #[default]
     Print,
     /// Build a new PATH from directories
-    New { directories: Vec<String> },
+    New { directories: Vec<OsString> },
     /// Add directories to front of PATH
-    Add { directories: Vec<String> },
+    Add { directories: Vec<OsString> },
     /// Add directories to back of PATH
-    Append { directories: Vec<String> },
+    Append { directories: Vec<OsString> },
     /// Analyze the current PATH
-    Analyze,
+    Analyze(AnalyzeArgs),
+    /// List commands that resolve differently in a new PATH
+    Impact {
+        new_path: OsString,
+
+        #[command(flatten)]
+        filter: CommandFilter,
+    },
+    /// List every command in the PATH with the directory it runs from
+    #[command(name = "commands")]
+    CommandList {
+        /// Only list commands whose names match this regular expression
+        #[arg(long, value_name = "PAT")]
+        regex: Option<String>,
+
+        #[command(flatten)]
+        filter: CommandFilter,
+    },
+    /// Explain how a command is found in the PATH
+    Why {
+        name: OsString,
+
+        #[command(flatten)]
+        filter: CommandFilter,
+    },
+    /// Show the file each command runs from
+    Which {
+        /// Also list the files shadowed by the one that runs
+        #[arg(short, long, default_value_t = false)]
+        all: bool,
+
+        #[arg(required = true)]
+        names: Vec<OsString>,
+
+        #[command(flatten)]
+        filter: CommandFilter,
+    },
+}
+
+/// Selects which files in a directory are treated as commands.
+#[derive(Args, Debug, PartialEq, Default, Clone, Copy)]
+struct CommandFilter {
+    /// Treat every file as a command, not just executables
+    #[arg(long, default_value_t = false)]
+    all_files: bool,
+
+    /// Ignore symbolic links to executables
+    #[arg(long, default_value_t = false)]
+    no_symlinks: bool,
+}
+
+impl CommandFilter {
+    fn accepts(&self, path: &Path) -> bool {
+        if self.no_symlinks && path.is_symlink() {
+            false
+        } else {
+            self.all_files || is_executable(path)
+        }
+    }
+}
+
+#[derive(Args, Debug, PartialEq, Default, Clone)]
+struct AnalyzeArgs {
+    /// Exit with a nonzero status if problems are found.
+    /// The status is 10 for invalid, 11 for duplicates, 12 for shadows,
+    /// 13 for relative, 14 for permissions, 15 for hijack, 16 for executables,
+    /// 17 for symlinks, 18 for aliases and 19 for unreadable, using the
+    /// first failing section
+    #[arg(long, default_value_t = false)]
+    check: bool,
+
+    /// Sections that cause --check to fail
+    /// [default: whichever of invalid and duplicates are run]
+    #[arg(long, value_delimiter = ',')]
+    fail_on: Option<Vec<Section>>,
+
+    /// Directories, file names or file paths of accepted problems to leave out
+    #[arg(long, value_delimiter = ',')]
+    ignore: Vec<OsString>,
+
+    /// Run only these sections
+    #[arg(long, value_delimiter = ',', conflicts_with = "skip")]
+    only: Vec<Section>,
+
+    /// Skip these sections
+    #[arg(long, value_delimiter = ',')]
+    skip: Vec<Section>,
+
+    /// Format of the report
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+
+    /// How to report shadowed files that run the same program
+    #[arg(long, value_enum, default_value_t = BenignShadows::Show)]
+    benign: BenignShadows,
+
+    /// Show how many commands each directory has, shadows and loses to shadowing.
+    /// Only the text, html and markdown formats include the statistics
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
+    #[command(flatten)]
+    filter: CommandFilter,
+}
+
+impl AnalyzeArgs {
+    /// Returns the sections selected by --only and --skip in report order.
+    fn sections(&self) -> Vec<Section> {
+        Section::value_variants()
+            .iter()
+            .copied()
+            .filter(|s| self.only.is_empty() || self.only.contains(s))
+            .filter(|s| !self.skip.contains(s))
+            .collect()
+    }
+
+    /// Returns the sections given with --fail-on, or by default the
+    /// invalid and duplicates sections that are run.
+    fn fail_on(&self) -> Vec<Section> {
+        match &self.fail_on {
+            Some(fail_on) => fail_on.clone(),
+            None => [Section::Invalid, Section::Duplicates]
+                .into_iter()
+                .filter(|s| self.sections().contains(s))
+                .collect(),
+        }
+    }
+}
+
+/// Sections of the analyze report.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Invalid,
+    #[value(alias = "duplicate")]
+    Duplicates,
+    #[value(alias = "shadow")]
+    Shadows,
+    Relative,
+    #[value(alias = "perms")]
+    Permissions,
+    Hijack,
+    Executables,
+    Symlinks,
+    Aliases,
+    Unreadable,
+}
+
+/// Treatment of shadows that are the same file, a symlink to
+/// the same file, or a file with identical content.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum BenignShadows {
+    /// List them with the other shadowed files
+    #[default]
+    Show,
+    /// Leave them out of the report
+    Hide,
+    /// List them separately after the other shadowed files
+    Group,
 }
 
-fn main() -> Result<()> {
+impl Section {
+    fn exit_code(self) -> u8 {
+        match self {
+            Section::Invalid => 10,
+            Section::Duplicates => 11,
+            Section::Shadows => 12,
+            Section::Relative => 13,
+            Section::Permissions => 14,
+            Section::Hijack => 15,
+            Section::Executables => 16,
+            Section::Symlinks => 17,
+            Section::Aliases => 18,
+            Section::Unreadable => 19,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Section::Invalid => "Invalid Directories",
+            Section::Duplicates => "Duplicate Directories",
+            Section::Shadows => "Shadowed Files",
+            Section::Relative => "Relative Directories",
+            Section::Permissions => "Insecure Directory Permissions",
+            Section::Hijack => "Hijack Risks",
+            Section::Executables => "Executable Problems",
+            Section::Symlinks => "Broken Symlinks",
+            Section::Aliases => "Directory Aliases",
+            Section::Unreadable => "Unreadable Directories",
+        }
+    }
+}
+
+/// Output formats of the analyze report.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ReportFormat {
+    /// Indented text
+    #[default]
+    Text,
+    /// One JSON object per finding per line
+    #[value(alias = "json-lines")]
+    Jsonl,
+    /// SARIF 2.1.0 log
+    Sarif,
+    /// Self-contained HTML page
+    Html,
+    /// Markdown document
+    #[value(alias = "md")]
+    Markdown,
+}
+
+fn main() -> Result<ExitCode> {
     main_logic(Cli::parse(), &mut stdout())
 }
 
-fn main_logic(cli: Cli, output: &mut impl Write) -> Result<()> {
-    let current_path_str = env::var(cli.env).unwrap_or_default();
-    let current = parse_path(&current_path_str);
+fn main_logic(cli: Cli, output: &mut impl Write) -> Result<ExitCode> {
+    let (current_path, source) = cli.read_path()?;
+    let current = parse_path(&current_path);
+    let stages = cli.stages();
+    let on_error = cli.on_error;
     let pretty = cli.pretty || cli.command == Commands::Print;
     let mut path = match cli.command {
         Commands::Print => current,
         Commands::New { directories } => exec_new(directories),
         Commands::Add { directories } => exec_add(&current, directories),
         Commands::Append { directories } => exec_append(&current, directories),
-        Commands::Analyze => exec_analyze(&current_path_str, output)?,
+        Commands::Analyze(args) => {
+            return exec_analyze(&current_path, &cli.env, source, &args, output);
+        }
+        Commands::Impact { new_path, filter } => {
+            exec_impact(&current, &parse_path(&new_path), filter, output)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Commands::CommandList { regex, filter } => {
+            exec_commands(&current, regex.as_deref(), filter, output)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Commands::Why { name, filter } => {
+            return exec_why(&current_path, &cli.env, &name, filter, output);
+        }
+        Commands::Which { all, names, filter } => {
+            return exec_which(&current_path, &names, all, filter, output);
+        }
     };
-    path = apply_filters(path, cli.filter, cli.normalize);
+    path = apply_filters(path, &stages, on_error)?;
     if pretty {
-        exec_print(path, output)
+        exec_print(path, output)?;
     } else {
-        writeln!(output, "{}", to_string(&path)).with_context(|| "Failed to write output")
+        output
+            .write_all(&[to_os_string(&path).as_bytes(), b"\n"].concat())
+            .with_context(|| "Failed to write output")?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn exec_analyze(
+    path_str: &OsStr,
+    env: &str,
+    source: PathSource,
+    args: &AnalyzeArgs,
+    output: &mut impl Write,
+) -> Result<ExitCode> {
+    let sections = args.sections();
+    if args.check
+        && let Some(section) = args.fail_on().iter().find(|s| !sections.contains(s))
+    {
+        return Err(anyhow!(
+            "--fail-on {} names a section that is not run",
+            section.to_possible_value().unwrap().get_name()
+        ));
+    }
+    if args.stats && matches!(args.format, ReportFormat::Jsonl | ReportFormat::Sarif) {
+        return Err(anyhow!(
+            "--stats cannot be used with --format {}",
+            args.format.to_possible_value().unwrap().get_name()
+        ));
+    }
+    let mut analysis = Analysis::new(path_str, &sections, args.filter)?;
+    analysis.set_source(env, source);
+    if args.stats {
+        analysis.stats = Some(get_dir_stats(path_str, args.filter));
+    }
+    analysis.set_benign(args.benign);
+    analysis.ignore(&args.ignore);
+
+    match args.format {
+        ReportFormat::Text => write_analysis(output, &analysis)?,
+        ReportFormat::Jsonl => report::write_json_lines(output, &report::findings(&analysis))?,
+        ReportFormat::Sarif => report::write_sarif(output, &report::findings(&analysis))?,
+        ReportFormat::Html => report::write_html(output, &analysis)?,
+        ReportFormat::Markdown => report::write_markdown(output, &analysis)?,
+    }
+
+    if !args.check {
+        return Ok(ExitCode::SUCCESS);
+    }
+    match analysis.failed_section(&args.fail_on()) {
+        Some(section) => Ok(ExitCode::from(section.exit_code())),
+        None => Ok(ExitCode::SUCCESS),
+    }
+}
+
+fn write_analysis(output: &mut impl Write, analysis: &Analysis) -> Result<()> {
+    if let Some(note) = analysis.source_note() {
+        writeln!(output, "Note: {note}\n")?;
+    }
+    for (i, section) in analysis.sections.iter().enumerate() {
+        if i > 0 {
+            writeln!(output)?;
+        }
+        match section {
+            Section::Invalid => {
+                write_invalid(output, &analysis.invalid)?;
+            }
+            Section::Duplicates => {
+                write_dirs(output, *section, &analysis.duplicates)?;
+            }
+            Section::Shadows => {
+                for (i, (title, shadows)) in analysis.shadow_groups().iter().enumerate() {
+                    if i > 0 {
+                        writeln!(output)?;
+                    }
+                    write_shadows(output, title, shadows)?;
+                }
+            }
+            Section::Relative => {
+                write_relative(output, &analysis.relative)?;
+            }
+            Section::Permissions => {
+                write_permissions(output, &analysis.permissions)?;
+            }
+            Section::Hijack => {
+                write_hijack_risks(output, &analysis.hijack)?;
+            }
+            Section::Executables => {
+                write_file_problems(output, &analysis.executables)?;
+            }
+            Section::Symlinks => {
+                write_broken_symlinks(output, &analysis.symlinks)?;
+            }
+            Section::Aliases => {
+                write_aliases(output, &analysis.aliases)?;
+            }
+            Section::Unreadable => {
+                write_unreadable(output, &analysis.unreadable)?;
+            }
+        }
+    }
+    if let Some(stats) = &analysis.stats {
+        if !analysis.sections.is_empty() {
+            writeln!(output)?;
+        }
+        write_stats(output, stats)?;
+    }
+    Ok(())
+}
+
+fn write_stats(output: &mut impl Write, stats: &[DirStats]) -> Result<()> {
+    writeln!(output, "Directory Statistics:")?;
+    if stats.is_empty() {
+        writeln!(output, "    None")?;
+        return Ok(());
+    }
+    writeln!(
+        output,
+        "    commands  reachable  shadows  shadowed  directory"
+    )?;
+    for s in stats {
+        writeln!(
+            output,
+            "    {:>8}  {:>9}  {:>7}  {:>8}  {}",
+            s.commands,
+            s.reachable,
+            s.shadows,
+            s.shadowed,
+            s.dir.display()
+        )?;
+    }
+    Ok(())
+}
+
+fn write_dirs(output: &mut impl Write, section: Section, dirs: &[OsString]) -> Result<()> {
+    writeln!(output, "{}:", section.title())?;
+    if dirs.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for dir in dirs {
+            writeln!(output, "    {}", dir.display())?;
+        }
+    }
+    Ok(())
+}
+
+fn write_invalid(output: &mut impl Write, invalid: &[InvalidDir]) -> Result<()> {
+    writeln!(output, "{}:", Section::Invalid.title())?;
+    if invalid.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for i in invalid {
+            writeln!(output, "    {}  ({})", i.dir.display(), i.describe())?;
+        }
+    }
+    Ok(())
+}
+
+fn write_shadows(
+    output: &mut impl Write,
+    title: &str,
+    shadows: &[(OsString, Vec<Shadow>)],
+) -> Result<()> {
+    writeln!(output, "{}:", title)?;
+    if shadows.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for (i, (dir, dir_shadows)) in shadows.iter().enumerate() {
+            if i > 0 {
+                writeln!(output)?;
+            }
+            writeln!(output, "    {}", dir.display())?;
+            for s in dir_shadows {
+                if s.kind.is_benign() {
+                    writeln!(
+                        output,
+                        "        {}  =>  {}  ({})",
+                        s.file.display(),
+                        s.owner_dir.display(),
+                        s.kind.describe()
+                    )?;
+                } else {
+                    writeln!(
+                        output,
+                        "        {}  =>  {}",
+                        s.file.display(),
+                        s.owner_dir.display()
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_relative(output: &mut impl Write, relative: &[RelativeDir]) -> Result<()> {
+    writeln!(output, "{}:", Section::Relative.title())?;
+    if relative.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for r in relative {
+            writeln!(output, "    {}  ({})", r.display_dir(), r.reason.describe())?;
+        }
+    }
+    Ok(())
+}
+
+fn write_permissions(output: &mut impl Write, permissions: &[InsecureDir]) -> Result<()> {
+    writeln!(output, "{}:", Section::Permissions.title())?;
+    if permissions.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for p in permissions {
+            writeln!(
+                output,
+                "    {}  ({})",
+                p.dir.display(),
+                p.problem.describe()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn write_unreadable(output: &mut impl Write, unreadable: &[UnreadableDir]) -> Result<()> {
+    writeln!(output, "{}:", Section::Unreadable.title())?;
+    if unreadable.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for u in unreadable {
+            writeln!(output, "    {}  ({})", u.dir.display(), u.error)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_hijack_risks(output: &mut impl Write, risks: &[HijackRisk]) -> Result<()> {
+    writeln!(output, "{}:", Section::Hijack.title())?;
+    if risks.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for (i, risk) in risks.iter().enumerate() {
+            if i > 0 {
+                writeln!(output)?;
+            }
+            writeln!(
+                output,
+                "    {}  ({})",
+                risk.dir.display(),
+                risk.describe_writers()
+            )?;
+            for (command, dir) in &risk.commands {
+                writeln!(
+                    output,
+                    "        {}  =>  {}",
+                    command.display(),
+                    dir.display()
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_file_problems(
+    output: &mut impl Write,
+    problems: &[(OsString, Vec<FileProblem>)],
+) -> Result<()> {
+    writeln!(output, "{}:", Section::Executables.title())?;
+    if problems.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for (i, (dir, dir_problems)) in problems.iter().enumerate() {
+            if i > 0 {
+                writeln!(output)?;
+            }
+            writeln!(output, "    {}", dir.display())?;
+            for p in dir_problems {
+                writeln!(
+                    output,
+                    "        {}  ({})",
+                    p.file.display(),
+                    p.kind.describe()
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_broken_symlinks(
+    output: &mut impl Write,
+    symlinks: &[(OsString, Vec<BrokenSymlink>)],
+) -> Result<()> {
+    writeln!(output, "{}:", Section::Symlinks.title())?;
+    if symlinks.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for (i, (dir, dir_symlinks)) in symlinks.iter().enumerate() {
+            if i > 0 {
+                writeln!(output)?;
+            }
+            writeln!(output, "    {}", dir.display())?;
+            for s in dir_symlinks {
+                writeln!(output, "        {}", s.describe())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_aliases(output: &mut impl Write, aliases: &[AliasGroup]) -> Result<()> {
+    writeln!(output, "{}:", Section::Aliases.title())?;
+    if aliases.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for (i, group) in aliases.iter().enumerate() {
+            if i > 0 {
+                writeln!(output)?;
+            }
+            writeln!(
+                output,
+                "    {}  ({})",
+                group.target.display(),
+                group.kind.describe()
+            )?;
+            for (j, dir) in group.dirs.iter().enumerate() {
+                if j == 0 {
+                    writeln!(output, "        {}  (wins)", dir.display())?;
+                } else {
+                    writeln!(output, "        {}", dir.display())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn exec_impact(
+    current: &[OsString],
+    proposed: &[OsString],
+    filter: CommandFilter,
+    output: &mut impl Write,
+) -> Result<()> {
+    let changes = get_impact(current, proposed, filter);
+
+    writeln!(output, "Changed Commands:")?;
+    let changed = changes
+        .iter()
+        .filter_map(|c| match (&c.old_dir, &c.new_dir) {
+            (Some(old_dir), Some(new_dir)) => Some((&c.command, old_dir, new_dir)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if changed.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for (command, old_dir, new_dir) in changed {
+            writeln!(
+                output,
+                "    {}  {}  =>  {}",
+                command.display(),
+                old_dir.display(),
+                new_dir.display()
+            )?;
+        }
+    }
+
+    writeln!(output)?;
+
+    writeln!(output, "Added Commands:")?;
+    let added = changes
+        .iter()
+        .filter_map(|c| match (&c.old_dir, &c.new_dir) {
+            (None, Some(new_dir)) => Some((&c.command, new_dir)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if added.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for (command, new_dir) in added {
+            writeln!(
+                output,
+                "    {}  =>  {}",
+                command.display(),
+                new_dir.display()
+            )?;
+        }
+    }
+
+    writeln!(output)?;
+
+    writeln!(output, "Removed Commands:")?;
+    let removed = changes
+        .iter()
+        .filter_map(|c| match (&c.old_dir, &c.new_dir) {
+            (Some(old_dir), None) => Some((&c.command, old_dir)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if removed.is_empty() {
+        writeln!(output, "    None")?;
+    } else {
+        for (command, old_dir) in removed {
+            writeln!(
+                output,
+                "    {}  =>  {}",
+                command.display(),
+                old_dir.display()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints each command in name order with the directory it runs from,
+/// separated by a tab, keeping only names that match the regex if given.
+fn exec_commands(
+    path: &[OsString],
+    regex: Option<&str>,
+    filter: CommandFilter,
+    output: &mut impl Write,
+) -> Result<()> {
+    let regex = regex
+        .map(regex::bytes::Regex::new)
+        .transpose()
+        .with_context(|| "Invalid --regex pattern")?;
+    let (commands, unreadable) = get_commands(path, filter);
+    warn_unreadable(&unreadable);
+    for (command, dir) in commands {
+        if let Some(regex) = &regex
+            && !regex.is_match(command.as_bytes())
+        {
+            continue;
+        }
+        output
+            .write_all(&[command.as_bytes(), b"\t", dir.as_bytes(), b"\n"].concat())
+            .with_context(|| format!("Failed to print {}", command.display()))?;
+    }
+    Ok(())
+}
+
+/// Prints what each path entry contributes to finding a command, the
+/// symbolic links followed to reach the one that runs and the file that
+/// runs.  Fails if the command is not found.
+fn exec_why(
+    path_str: &OsStr,
+    env: &str,
+    name: &OsStr,
+    filter: CommandFilter,
+    output: &mut impl Write,
+) -> Result<ExitCode> {
+    let steps = explain_command(path_str, name, filter);
+    writeln!(output, "Searching {} for {}:", env, name.display())?;
+    if steps.is_empty() {
+        writeln!(output, "    None")?;
+    }
+    let mut found = None;
+    for (dir, lookup) in &steps {
+        writeln!(output, "    {}  {}", dir.display(), lookup.describe(name))?;
+        if let Lookup::Runs(chain) = lookup {
+            for link in chain {
+                writeln!(output, "        ->  {}", link.display())?;
+            }
+            found = Some(Path::new(dir).join(name));
+        }
+    }
+    writeln!(output)?;
+    match found {
+        Some(file) => {
+            writeln!(output, "{} runs {}", name.display(), file.display())?;
+            Ok(ExitCode::SUCCESS)
+        }
+        None => {
+            writeln!(output, "{} is not found", name.display())?;
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+/// Prints the file each command runs from, or with all every matching
+/// file in search order.  Fails if any command is not found.
+fn exec_which(
+    path_str: &OsStr,
+    names: &[OsString],
+    all: bool,
+    filter: CommandFilter,
+    output: &mut impl Write,
+) -> Result<ExitCode> {
+    let mut status = ExitCode::SUCCESS;
+    for name in names {
+        let candidates = find_command(path_str, name, filter);
+        if candidates.is_empty() {
+            eprintln!("{} not found", name.display());
+            status = ExitCode::FAILURE;
+        }
+        let shown = if all { candidates.len() } else { 1 };
+        for candidate in candidates.iter().take(shown) {
+            let file = Path::new(&candidate.dir).join(name);
+            let note = match candidate.kind {
+                Some(kind) => format!("  (shadowed, {})", kind.describe()),
+                None => String::new(),
+            };
+            output
+                .write_all(&[file.as_os_str().as_bytes(), note.as_bytes(), b"\n"].concat())
+                .with_context(|| format!("Failed to print {}", file.display()))?;
+        }
+    }
+    Ok(status)
+}
+
+/// Writes each directory exactly as it appears in the path so names
+/// that are not valid UTF-8 survive a round trip through the shell.
+fn exec_print(current: Vec<OsString>, output: &mut impl Write) -> Result<()> {
+    for dir in current {
+        output
+            .write_all(&[dir.as_bytes(), b"\n"].concat())
+            .with_context(|| format!("Failed to print {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+fn exec_new(directories: Vec<OsString>) -> Vec<OsString> {
+    let mut path = Vec::new();
+    parse_and_add_all_last(&mut path, directories);
+    path
+}
+
+fn exec_add(current: &[OsString], directories: Vec<OsString>) -> Vec<OsString> {
+    let mut path = Vec::new();
+    parse_and_add_all_last(&mut path, directories);
+    add_all_unique(&mut path, current);
+    path
+}
+
+fn exec_append(current: &[OsString], directories: Vec<OsString>) -> Vec<OsString> {
+    let mut path = Vec::new();
+    add_all_unique(&mut path, current);
+    parse_and_add_all_last(&mut path, directories);
+    path
+}
+
+fn remove(path: &mut Vec<OsString>, dir: &OsStr) {
+    path.retain(|x| x != dir);
+}
+
+fn apply_filters(
+    path: Vec<OsString>,
+    stages: &[Stage],
+    on_error: OnError,
+) -> Result<Vec<OsString>> {
+    stages
+        .iter()
+        .try_fold(path, |path, stage| stage.apply(path, on_error))
+}
+
+fn parse_and_add_all_last(path: &mut Vec<OsString>, directories: Vec<OsString>) {
+    directories
+        .iter()
+        .map(|arg| parse_path(arg))
+        .for_each(|dirs| add_all_last(path, &dirs));
+}
+
+fn add_last(path: &mut Vec<OsString>, dir: &OsStr) {
+    remove(path, dir);
+    path.push(dir.to_os_string());
+}
+
+fn add_all_last(path: &mut Vec<OsString>, other: &[OsString]) {
+    other.iter().for_each(|x| add_last(path, x));
+}
+
+fn add_all_unique(path: &mut Vec<OsString>, other: &[OsString]) {
+    other.iter().for_each(|x| add_unique(path, x));
+}
+
+fn add_unique(path: &mut Vec<OsString>, dir: &OsStr) {
+    if !(dir.is_empty() || path.iter().any(|s| s == dir)) {
+        path.push(dir.to_os_string());
+    }
+}
+
+fn parse_path(source: &OsStr) -> Vec<OsString> {
+    parse_raw_path(source).into_iter().unique().collect()
+}
+
+fn parse_raw_path(source: &OsStr) -> Vec<OsString> {
+    split_path(source)
+        .into_iter()
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Splits a path on colons without requiring it to be valid UTF-8.
+fn split_path(source: &OsStr) -> Vec<OsString> {
+    source
+        .as_bytes()
+        .split(|b| *b == b':')
+        .map(|x| OsStr::from_bytes(x).to_os_string())
+        .collect()
+}
+
+fn to_os_string(path: &[OsString]) -> OsString {
+    path.join(OsStr::new(":"))
+}
+
+fn is_valid(path: &OsStr) -> Result<bool> {
+    match fs::metadata(Path::new(path)) {
+        Ok(metadata) => Ok(metadata.is_dir()),
+        Err(e) if is_missing(&e) => Ok(false),
+        Err(e) => Err(e).context("unable to read metadata"),
+    }
+}
+
+/// True when an error means that nothing usable exists at the path, as
+/// opposed to something that exists but cannot be read.
+fn is_missing(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory | io::ErrorKind::InvalidInput
+    ) || error.raw_os_error() == Some(libc::ELOOP)
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    unsafe { libc::geteuid() }
+}
+
+/// Returns true if anyone other than the owner can write to the file.
+fn is_writable_by_others(mode: u32) -> bool {
+    mode & 0o022 != 0
+}
+
+/// Permission problems that let someone other than the current user
+/// or root add or replace commands in a directory.
+#[derive(Debug, Clone, PartialEq)]
+enum PermissionProblem {
+    GroupWritable,
+    WorldWritable,
+    OtherOwner(u32),
+    WritableParent(PathBuf),
+}
+
+impl PermissionProblem {
+    fn describe(&self) -> String {
+        match self {
+            PermissionProblem::GroupWritable => "writable by group".to_string(),
+            PermissionProblem::WorldWritable => "writable by everyone".to_string(),
+            PermissionProblem::OtherOwner(uid) => format!("owned by uid {}", uid),
+            PermissionProblem::WritableParent(parent) => {
+                format!("parent {} is writable by others", parent.display())
+            }
+        }
+    }
+}
+
+/// Holds a directory with a permission problem.
+#[derive(Debug, Clone, PartialEq)]
+struct InsecureDir {
+    dir: OsString,
+    problem: PermissionProblem,
+}
+
+impl InsecureDir {
+    fn new(dir: OsString, problem: PermissionProblem) -> Self {
+        Self { dir, problem }
+    }
+}
+
+fn is_writable_by_current_user(dir: &OsStr) -> bool {
+    match CString::new(dir.as_bytes()) {
+        // SAFETY: the pointer is a valid NUL terminated string for the call.
+        Ok(c_dir) => unsafe { libc::access(c_dir.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+/// Returns true if the current user can execute the file.
+/// Symbolic links are followed.
+fn is_executable(path: &Path) -> bool {
+    match CString::new(path.as_os_str().as_bytes()) {
+        // SAFETY: the pointer is a valid NUL terminated string for the call.
+        Ok(c_path) => unsafe { libc::access(c_path.as_ptr(), libc::X_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+fn is_root_owned(dir: &OsStr) -> bool {
+    fs::metadata(Path::new(dir))
+        .map(|m| m.uid() == 0)
+        .unwrap_or(false)
+}
+
+/// Who other than root can add files to a directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Writer {
+    CurrentUser,
+    Group,
+    Everyone,
+}
+
+impl Writer {
+    fn describe(self) -> &'static str {
+        match self {
+            Writer::CurrentUser => "writable by you",
+            Writer::Group => "writable by group",
+            Writer::Everyone => "writable by everyone",
+        }
+    }
+}
+
+/// Root can write anywhere so it is never reported as a writer.
+fn get_writers(dir: &OsStr) -> Result<Vec<Writer>> {
+    let mode = fs::metadata(Path::new(dir))
+        .context("unable to read metadata")?
+        .mode();
+    let mut writers = Vec::new();
+    if current_uid() != 0 && is_writable_by_current_user(dir) {
+        writers.push(Writer::CurrentUser);
+    }
+    if mode & 0o020 != 0 {
+        writers.push(Writer::Group);
+    }
+    if mode & 0o002 != 0 {
+        writers.push(Writer::Everyone);
+    }
+    Ok(writers)
+}
+
+/// Holds a writable directory along with the commands, and the
+/// root-owned directories they currently resolve to, that anyone who
+/// can write to it could shadow.
+#[derive(Debug, Clone, PartialEq)]
+struct HijackRisk {
+    dir: OsString,
+    writers: Vec<Writer>,
+    commands: Vec<(OsString, OsString)>,
+}
+
+impl HijackRisk {
+    fn new(dir: OsString, writers: Vec<Writer>, commands: Vec<(OsString, OsString)>) -> Self {
+        Self {
+            dir,
+            writers,
+            commands,
+        }
+    }
+
+    fn describe_writers(&self) -> String {
+        self.writers.iter().map(|w| w.describe()).join(", ")
+    }
+}
+
+fn get_hijack_risks(path_str: &OsStr, filter: CommandFilter) -> Result<Vec<HijackRisk>> {
+    let path = parse_path(path_str);
+    let (commands, unreadable) = get_commands(&path, filter);
+    warn_unreadable(&unreadable);
+    let mut risks = Vec::new();
+    for (i, dir) in path.iter().enumerate() {
+        if !is_valid(dir).unwrap_or(false) {
+            continue;
+        }
+        let writers = get_writers(dir)?;
+        if writers.is_empty() {
+            continue;
+        }
+        let trusted = path[i + 1..]
+            .iter()
+            .filter(|d| is_root_owned(d))
+            .collect::<HashSet<&OsString>>();
+        let hijackable = commands
+            .iter()
+            .filter(|(_, d)| trusted.contains(d))
+            .map(|(c, d)| (c.to_os_string(), d.to_os_string()))
+            .collect::<Vec<(OsString, OsString)>>();
+        if !hijackable.is_empty() {
+            risks.push(HijackRisk::new(dir.to_os_string(), writers, hijackable));
+        }
+    }
+    Ok(risks)
+}
+
+/// Problems with the mode of a file in a path directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileProblemKind {
+    NotExecutable,
+    WritableByOthers,
+    SetIdInWritableDir,
+}
+
+impl FileProblemKind {
+    fn describe(self) -> &'static str {
+        match self {
+            FileProblemKind::NotExecutable => "not executable",
+            FileProblemKind::WritableByOthers => "executable writable by others",
+            FileProblemKind::SetIdInWritableDir => "setuid or setgid in a writable directory",
+        }
+    }
+}
+
+/// Holds a file in a path directory with a problem in its mode.
+#[derive(Debug, Clone, PartialEq)]
+struct FileProblem {
+    file: OsString,
+    kind: FileProblemKind,
+}
+
+impl FileProblem {
+    fn new(file: OsString, kind: FileProblemKind) -> Self {
+        Self { file, kind }
     }
 }
 
-fn exec_analyze(path_str: &str, output: &mut impl Write) -> Result<Vec<String>> {
-    let invalids = get_invalid_dirs(path_str);
-    writeln!(output, "Invalid Directories:")?;
-    if invalids.is_empty() {
-        writeln!(output, "    None")?;
-    } else {
-        for invalid in invalids {
-            writeln!(output, "    {}", invalid)?;
+fn get_file_problems(path_str: &OsStr) -> Result<Vec<(OsString, Vec<FileProblem>)>> {
+    let mut all_problems = Vec::new();
+    for dir in parse_path(path_str) {
+        if !is_valid(&dir).unwrap_or(false) {
+            continue;
+        }
+        let dir_is_writable = !get_writers(&dir)?.is_empty();
+        let mut problems = Vec::new();
+        let Ok(files) = files_in_dir(&dir) else {
+            continue;
+        };
+        for file in files {
+            let mode = fs::metadata(Path::new(&dir).join(&file))?.mode();
+            if mode & 0o111 == 0 {
+                problems.push(FileProblem::new(file, FileProblemKind::NotExecutable));
+                continue;
+            }
+            if is_writable_by_others(mode) {
+                problems.push(FileProblem::new(
+                    file.to_os_string(),
+                    FileProblemKind::WritableByOthers,
+                ));
+            }
+            if dir_is_writable && mode & 0o6000 != 0 {
+                problems.push(FileProblem::new(file, FileProblemKind::SetIdInWritableDir));
+            }
+        }
+        if !problems.is_empty() {
+            all_problems.push((dir, problems));
         }
     }
+    Ok(all_problems)
+}
 
-    writeln!(output)?;
+fn get_permission_problems(dir: &OsStr) -> Result<Vec<PermissionProblem>> {
+    let mut problems = Vec::new();
+    let metadata = fs::metadata(Path::new(dir)).context("unable to read metadata")?;
+    let mode = metadata.mode();
+    if mode & 0o020 != 0 {
+        problems.push(PermissionProblem::GroupWritable);
+    }
+    if mode & 0o002 != 0 {
+        problems.push(PermissionProblem::WorldWritable);
+    }
+    let uid = metadata.uid();
+    if uid != 0 && uid != current_uid() {
+        problems.push(PermissionProblem::OtherOwner(uid));
+    }
 
-    let duplicates = get_duplicate_dirs(path_str);
-    writeln!(output, "Duplicate Directories:")?;
-    if duplicates.is_empty() {
-        writeln!(output, "    None")?;
-    } else {
-        for invalid in duplicates {
-            writeln!(output, "    {}", invalid)?;
+    // A parent that others can write to lets them rename the directory and
+    // put another in its place, unless the sticky bit prevents it.
+    let canonical = fs::canonicalize(Path::new(dir))?;
+    for parent in canonical.ancestors().skip(1) {
+        let mode = fs::metadata(parent)?.mode();
+        if is_writable_by_others(mode) && mode & 0o1000 == 0 {
+            problems.push(PermissionProblem::WritableParent(parent.to_path_buf()));
         }
     }
+    Ok(problems)
+}
 
-    writeln!(output)?;
+fn get_insecure_dirs(path_str: &OsStr) -> Vec<InsecureDir> {
+    parse_path(path_str)
+        .into_iter()
+        .filter(|d| is_valid(d).unwrap_or(false))
+        .flat_map(|d| {
+            get_permission_problems(&d)
+                .unwrap_or_default()
+                .into_iter()
+                .map(move |p| InsecureDir::new(d.to_os_string(), p))
+        })
+        .collect()
+}
 
-    let shadows = get_shadowed(path_str)?;
-    writeln!(output, "Shadowed Files:")?;
-    if shadows.is_empty() {
-        writeln!(output, "    None")?;
+fn canonicalize(path: &OsStr) -> Result<Option<OsString>> {
+    if !is_valid(path)? {
+        Ok(None)
     } else {
-        for (i, (dir, dir_shadows)) in shadows.iter().enumerate() {
-            if i > 0 {
-                writeln!(output)?;
-            }
-            writeln!(output, "    {}", dir)?;
-            for s in dir_shadows {
-                writeln!(output, "        {}  =>  {}", s.file, s.owner_dir)?;
-            }
-        }
+        let canonical = fs::canonicalize(Path::new(path))?;
+        Ok(Some(canonical.into_os_string()))
     }
+}
 
-    Ok(Vec::new())
+/// Reasons that a path entry does not name a usable directory.
+/// Each one calls for a different fix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InvalidReason {
+    Missing,
+    RegularFile,
+    NotDirectory,
+    BrokenSymlink,
+    SymlinkLoop,
+    PermissionDenied,
+    Unreadable,
 }
 
-fn exec_print(current: Vec<String>, output: &mut impl Write) -> Result<()> {
-    for dir in current {
-        writeln!(output, "{}", dir).with_context(|| format!("Failed to print {}", dir))?;
+impl InvalidReason {
+    fn describe(self) -> &'static str {
+        match self {
+            InvalidReason::Missing => "does not exist",
+            InvalidReason::RegularFile => "is a regular file",
+            InvalidReason::NotDirectory => "is not a directory",
+            InvalidReason::BrokenSymlink => "is a broken symlink",
+            InvalidReason::SymlinkLoop => "is a symlink loop",
+            InvalidReason::PermissionDenied => "permission denied",
+            InvalidReason::Unreadable => "metadata cannot be read",
+        }
     }
-    Ok(())
 }
 
-fn exec_new(directories: Vec<String>) -> Vec<String> {
-    let mut path = Vec::new();
-    parse_and_add_all_last(&mut path, directories);
-    path
+/// Holds a path entry that does not name a usable directory
+/// along with an existing directory that it may have meant.
+#[derive(Debug, Clone, PartialEq)]
+struct InvalidDir {
+    dir: OsString,
+    reason: InvalidReason,
+    suggestion: Option<OsString>,
 }
 
-fn exec_add(current: &[String], directories: Vec<String>) -> Vec<String> {
-    let mut path = Vec::new();
-    parse_and_add_all_last(&mut path, directories);
-    add_all_unique(&mut path, current);
-    path
+impl InvalidDir {
+    fn new(dir: OsString, reason: InvalidReason, suggestion: Option<OsString>) -> Self {
+        Self {
+            dir,
+            reason,
+            suggestion,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match &self.suggestion {
+            Some(suggestion) => format!(
+                "{}, did you mean {}?",
+                self.reason.describe(),
+                suggestion.display()
+            ),
+            None => self.reason.describe().to_string(),
+        }
+    }
 }
 
-fn exec_append(current: &[String], directories: Vec<String>) -> Vec<String> {
-    let mut path = Vec::new();
-    add_all_unique(&mut path, current);
-    parse_and_add_all_last(&mut path, directories);
-    path
+/// Uses the same metadata lookup as is_valid, then looks more closely
+/// at whatever made it fail.
+fn get_invalid_reason(dir: &OsStr) -> Option<InvalidReason> {
+    let path = Path::new(dir);
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => None,
+        Ok(metadata) if metadata.is_file() => Some(InvalidReason::RegularFile),
+        Ok(_) => Some(InvalidReason::NotDirectory),
+        Err(e) if e.raw_os_error() == Some(libc::ELOOP) => Some(InvalidReason::SymlinkLoop),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            Some(InvalidReason::PermissionDenied)
+        }
+        Err(_) if path.is_symlink() => match symlink_chain(path) {
+            (_, true) => Some(InvalidReason::SymlinkLoop),
+            (_, false) => Some(InvalidReason::BrokenSymlink),
+        },
+        Err(e) if is_missing(&e) => Some(InvalidReason::Missing),
+        Err(_) => Some(InvalidReason::Unreadable),
+    }
 }
 
-fn remove(path: &mut Vec<String>, dir: &str) {
-    path.retain(|x| x != dir);
+fn get_invalid_dirs(path_str: &OsStr) -> Vec<InvalidDir> {
+    parse_raw_path(path_str)
+        .into_iter()
+        .filter_map(|dir| {
+            let reason = get_invalid_reason(&dir)?;
+            let suggestion = match reason {
+                InvalidReason::Missing => suggest_dir(&dir),
+                _ => None,
+            };
+            Some(InvalidDir::new(dir, reason, suggestion))
+        })
+        .collect()
 }
 
-fn apply_filters(
-    path: Vec<String>,
-    filter_requested: bool,
-    normalize_requested: bool,
-) -> Vec<String> {
-    if filter_requested {
-        filter(path)
-    } else if normalize_requested {
-        normalize(path)
+/// Finds an existing directory that a missing entry probably meant by
+/// replacing its first missing component with a sibling.  Siblings that
+/// differ only in their version numbers win, taking the newest version,
+/// otherwise the sibling with the smallest edit distance is used.
+fn suggest_dir(dir: &OsStr) -> Option<OsString> {
+    let mut components = Path::new(dir).components();
+    let mut parent = PathBuf::new();
+    let name = loop {
+        let component = components.next()?;
+        let next = parent.join(component);
+        if !next.is_dir() {
+            break component.as_os_str().to_str()?.to_string();
+        }
+        parent = next;
+    };
+    let rest = components.as_path();
+    let list_dir = if parent.as_os_str().is_empty() {
+        Path::new(".")
     } else {
-        path
-    }
-}
+        parent.as_path()
+    };
+    let candidates = fs::read_dir(list_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|sibling| {
+            let mut candidate = parent.join(&sibling);
+            if !rest.as_os_str().is_empty() {
+                candidate.push(rest);
+            }
+            let candidate = candidate.into_os_string();
+            is_valid(&candidate)
+                .unwrap_or(false)
+                .then_some((sibling, candidate))
+        })
+        .collect::<Vec<(String, OsString)>>();
 
-fn filter(path: Vec<String>) -> Vec<String> {
-    path.into_iter()
-        .filter(|x| is_valid(x).ok() == Some(true))
-        .unique()
-        .collect::<Vec<String>>()
+    let digits = Regex::new(r"\d+").ok()?;
+    let pattern = digits.replace_all(&name, "#");
+    let version = |s: &str| {
+        digits
+            .find_iter(s)
+            .filter_map(|m| m.as_str().parse::<u64>().ok())
+            .collect::<Vec<u64>>()
+    };
+    let by_version = candidates
+        .iter()
+        .filter(|(sibling, _)| pattern != name && digits.replace_all(sibling, "#") == pattern)
+        .max_by_key(|(sibling, _)| version(sibling));
+    let by_distance = || {
+        candidates
+            .iter()
+            .filter(|(sibling, _)| edit_distance(&name, sibling) <= name.chars().count() / 3)
+            .min_by_key(|(sibling, _)| (edit_distance(&name, sibling), sibling.to_string()))
+    };
+    by_version
+        .or_else(by_distance)
+        .map(|(_, candidate)| candidate.to_os_string())
 }
 
-fn normalize(path: Vec<String>) -> Vec<String> {
-    path.into_iter()
-        .map(|x| canonicalize(&x).unwrap().unwrap_or_default())
-        .filter(|x| !x.is_empty())
-        .unique()
-        .collect::<Vec<String>>()
+/// Number of single character insertions, deletions or
+/// substitutions needed to turn one string into another.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
 }
 
-fn parse_and_add_all_last(path: &mut Vec<String>, directories: Vec<String>) {
-    directories
-        .iter()
-        .map(|arg| parse_path(arg))
-        .for_each(|dirs| add_all_last(path, &dirs));
+/// Reasons that a path entry lets the current directory decide which
+/// command runs, or otherwise does not name a fixed directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RelativeReason {
+    Empty,
+    Relative,
+    ParentComponent,
+    CurrentComponent,
 }
 
-fn add_last(path: &mut Vec<String>, dir: &str) {
-    remove(path, dir);
-    path.push(dir.to_string());
+impl RelativeReason {
+    fn describe(self) -> &'static str {
+        match self {
+            RelativeReason::Empty => "empty entry searches the current directory",
+            RelativeReason::Relative => "relative to the current directory",
+            RelativeReason::ParentComponent => "contains a .. component",
+            RelativeReason::CurrentComponent => "contains a . component",
+        }
+    }
 }
 
-fn add_all_last(path: &mut Vec<String>, other: &[String]) {
-    other.iter().for_each(|x| add_last(path, x));
+/// Holds a path entry that is not a plain absolute directory.
+#[derive(Debug, Clone, PartialEq)]
+struct RelativeDir {
+    dir: OsString,
+    reason: RelativeReason,
 }
 
-fn add_all_unique(path: &mut Vec<String>, other: &[String]) {
-    other.iter().for_each(|x| add_unique(path, x));
+impl RelativeDir {
+    fn new(dir: OsString, reason: RelativeReason) -> Self {
+        Self { dir, reason }
+    }
+
+    /// Empty entries are shown as a pair of quotes so they remain visible.
+    fn display_dir(&self) -> String {
+        if self.dir.is_empty() {
+            "\"\"".to_string()
+        } else {
+            self.dir.display().to_string()
+        }
+    }
 }
 
-fn add_unique(path: &mut Vec<String>, dir: &str) {
-    if !(dir.is_empty() || path.iter().any(|s| s == dir)) {
-        path.push(dir.to_string());
+fn get_relative_reason(dir: &OsStr) -> Option<RelativeReason> {
+    let dir = dir.as_bytes();
+    if dir.is_empty() {
+        Some(RelativeReason::Empty)
+    } else if !dir.starts_with(b"/") {
+        Some(RelativeReason::Relative)
+    } else if dir.split(|b| *b == b'/').any(|c| c == b"..") {
+        Some(RelativeReason::ParentComponent)
+    } else if dir.split(|b| *b == b'/').any(|c| c == b".") {
+        Some(RelativeReason::CurrentComponent)
+    } else {
+        None
     }
 }
 
-fn parse_path(source: &str) -> Vec<String> {
-    source
-        .split(":")
-        .filter(|x| !x.is_empty())
-        .map(|x| x.to_string())
-        .unique()
+/// Unlike the other checks this one keeps empty entries since the
+/// shell treats them as the current directory.
+fn get_relative_dirs(path_str: &OsStr) -> Vec<RelativeDir> {
+    if path_str.is_empty() {
+        return Vec::new();
+    }
+    split_path(path_str)
+        .into_iter()
+        .filter_map(|d| get_relative_reason(&d).map(|r| RelativeDir::new(d, r)))
         .collect()
 }
 
-fn parse_raw_path(source: &str) -> Vec<String> {
-    source
-        .split(":")
-        .filter(|x| !x.is_empty())
-        .map(|x| x.to_string())
-        .collect()
+/// Cleans a directory name without touching the file system by removing
+/// repeated and trailing slashes and . components, and by letting each ..
+/// component remove the component before it.
+fn clean_path(dir: &OsStr) -> OsString {
+    let dir = dir.as_bytes();
+    let absolute = dir.starts_with(b"/");
+    let mut components: Vec<&[u8]> = Vec::new();
+    for component in dir.split(|b| *b == b'/') {
+        match component {
+            b"" | b"." => {}
+            b".." => {
+                if components.last().is_some_and(|c| *c != b"..") {
+                    components.pop();
+                } else if !absolute {
+                    components.push(b"..");
+                }
+            }
+            _ => components.push(component),
+        }
+    }
+    let cleaned = components.join(&b'/');
+    if absolute {
+        OsString::from_vec([b"/".as_slice(), &cleaned].concat())
+    } else if cleaned.is_empty() {
+        OsString::from(".")
+    } else {
+        OsString::from_vec(cleaned)
+    }
 }
 
-fn to_string(path: &[String]) -> String {
-    path.join(":")
+/// Ways that different names in a path can refer to the same directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AliasKind {
+    Lexical,
+    Symlink,
 }
 
-fn is_valid(path: &str) -> Result<bool> {
-    if !Path::new(path).exists() {
-        Ok(false)
-    } else {
-        fs::metadata(Path::new(path))
-            .map(|d| d.is_dir())
-            .context("unable to read metadata")
+impl AliasKind {
+    fn describe(self) -> &'static str {
+        match self {
+            AliasKind::Lexical => "same name once cleaned",
+            AliasKind::Symlink => "same directory through symlinks",
+        }
     }
 }
 
-fn canonicalize(path: &str) -> Result<Option<String>> {
-    if !is_valid(path)? {
-        Ok(None)
-    } else {
-        let canonical = fs::canonicalize(Path::new(path))?;
-        let canonical = canonical.to_str().map(String::from);
-        let canonical = canonical.or_else(|| Some(path.to_string()));
-        Ok(canonical)
+/// Holds path entries that all name the same directory, in path order.
+/// The first entry is the one the shell searches, so it wins.
+#[derive(Debug, Clone, PartialEq)]
+struct AliasGroup {
+    target: OsString,
+    kind: AliasKind,
+    dirs: Vec<OsString>,
+}
+
+impl AliasGroup {
+    fn new(target: OsString, kind: AliasKind, dirs: Vec<OsString>) -> Self {
+        Self { target, kind, dirs }
     }
 }
 
-fn get_invalid_dirs(path_str: &str) -> Vec<String> {
-    let mut dirs = parse_raw_path(path_str);
-    dirs.retain(|x| !is_valid(x).unwrap_or(false));
-    dirs
+/// Groups entries by their canonical directory, or by their cleaned
+/// name for entries that do not exist.  Exact duplicates are left to
+/// get_duplicate_dirs.
+fn get_alias_groups(path_str: &OsStr) -> Vec<AliasGroup> {
+    let mut groups: Vec<(OsString, Vec<OsString>)> = Vec::new();
+    for dir in parse_path(path_str) {
+        let target = match canonicalize(&dir) {
+            Ok(Some(canonical)) => canonical,
+            _ => clean_path(&dir),
+        };
+        match groups.iter_mut().find(|(t, _)| *t == target) {
+            Some((_, dirs)) => dirs.push(dir),
+            None => groups.push((target, vec![dir])),
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|(_, dirs)| dirs.len() > 1)
+        .map(|(target, dirs)| {
+            let kind = if dirs.iter().map(|d| clean_path(d)).all_equal() {
+                AliasKind::Lexical
+            } else {
+                AliasKind::Symlink
+            };
+            AliasGroup::new(target, kind, dirs)
+        })
+        .collect()
 }
 
-fn get_duplicate_dirs(path_str: &str) -> Vec<String> {
+fn get_duplicate_dirs(path_str: &OsStr) -> Vec<OsString> {
     let mut visited = HashSet::new();
     parse_raw_path(path_str)
         .iter()
         .map(|d| (d, visited.insert(d)))
         .filter(|(_, added)| !added)
-        .map(|(d, _)| d.to_string())
+        .map(|(d, _)| d.to_os_string())
         .collect()
 }
 
-fn files_in_dir(dir: &str) -> Result<BTreeSet<String>> {
-    let filename_regex = Regex::new("[^/]+$")?;
+/// Returns the names of the files in a directory.  Symbolic links are
+/// followed so links to files are included while broken links are not.
+fn files_in_dir(dir: &OsStr) -> Result<BTreeSet<OsString>> {
     let mut files = BTreeSet::new();
     let dir_path = Path::new(dir);
-    if dir_path.exists() {
+    if dir_path.is_dir() {
         for entry in fs::read_dir(dir_path)? {
             let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(file_path) = path.to_str() {
-                    if let Some(cap) = filename_regex.captures(file_path) {
-                        files.insert(cap[0].to_string());
-                    }
-                }
+            if entry.path().is_file() {
+                files.insert(entry.file_name());
             }
         }
     }
     Ok(files)
 }
 
+/// Holds a symbolic link in a path directory that does not lead to a
+/// file, along with every link followed while trying to resolve it.
+#[derive(Debug, Clone, PartialEq)]
+struct BrokenSymlink {
+    file: OsString,
+    chain: Vec<PathBuf>,
+    looped: bool,
+}
+
+impl BrokenSymlink {
+    fn new(file: OsString, chain: Vec<PathBuf>, looped: bool) -> Self {
+        Self {
+            file,
+            chain,
+            looped,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let end = if self.looped { "(loop)" } else { "(missing)" };
+        let mut parts = vec![self.file.display().to_string()];
+        parts.extend(self.chain.iter().map(|s| s.display().to_string()));
+        format!("{} {}", parts.join("  ->  "), end)
+    }
+}
+
+/// The most symbolic links the kernel follows before giving up with ELOOP.
+const SYMLOOP_MAX: usize = 40;
+
+/// Follows a symbolic link one step at a time and returns each target
+/// along with whether the links form a loop.  Relative targets are
+/// joined to the directory containing the link.  Targets are compared
+/// after cleaning so that a loop through `..` is still found, and a
+/// chain longer than the kernel would follow counts as a loop.
+fn symlink_chain(path: &Path) -> (Vec<PathBuf>, bool) {
+    let mut chain = Vec::new();
+    let mut visited = HashSet::from([clean_path(path.as_os_str())]);
+    let mut current = path.to_path_buf();
+    while let Ok(target) = fs::read_link(&current) {
+        let next = match current.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+        chain.push(next.clone());
+        if !visited.insert(clean_path(next.as_os_str())) || chain.len() >= SYMLOOP_MAX {
+            return (chain, true);
+        }
+        current = next;
+    }
+    (chain, false)
+}
+
+fn broken_symlinks_in_dir(dir: &OsStr) -> Result<Vec<BrokenSymlink>> {
+    let mut symlinks = Vec::new();
+    for entry in fs::read_dir(Path::new(dir))? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_symlink() && !path.exists() {
+            let (chain, looped) = symlink_chain(&path);
+            symlinks.push(BrokenSymlink::new(entry.file_name(), chain, looped));
+        }
+    }
+    symlinks.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(symlinks)
+}
+
+fn get_broken_symlinks(path_str: &OsStr) -> Vec<(OsString, Vec<BrokenSymlink>)> {
+    let mut all_symlinks = Vec::new();
+    for dir in parse_path(path_str) {
+        if !is_valid(&dir).unwrap_or(false) {
+            continue;
+        }
+        let Ok(symlinks) = broken_symlinks_in_dir(&dir) else {
+            continue;
+        };
+        if !symlinks.is_empty() {
+            all_symlinks.push((dir, symlinks));
+        }
+    }
+    all_symlinks
+}
+
+/// Returns the files in a directory that the filter treats as commands.
+fn commands_in_dir(dir: &OsStr, filter: CommandFilter) -> Result<BTreeSet<OsString>> {
+    let mut files = files_in_dir(dir)?;
+    files.retain(|f| filter.accepts(&Path::new(dir).join(f)));
+    Ok(files)
+}
+
+/// How a shadowed file relates to the file that shadows it.
+/// Everything other than Different is harmless since the same
+/// program runs either way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ShadowKind {
+    SameFile,
+    Symlink,
+    IdenticalContent,
+    Different,
+}
+
+impl ShadowKind {
+    fn is_benign(self) -> bool {
+        self != ShadowKind::Different
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            ShadowKind::SameFile => "same file",
+            ShadowKind::Symlink => "symlink to same file",
+            ShadowKind::IdenticalContent => "identical content",
+            ShadowKind::Different => "different file",
+        }
+    }
+}
+
+fn classify_shadow(file: &Path, owner_file: &Path) -> ShadowKind {
+    let (Ok(meta), Ok(owner_meta)) = (fs::metadata(file), fs::metadata(owner_file)) else {
+        return ShadowKind::Different;
+    };
+    if meta.dev() == owner_meta.dev() && meta.ino() == owner_meta.ino() {
+        if file.is_symlink() || owner_file.is_symlink() {
+            ShadowKind::Symlink
+        } else {
+            ShadowKind::SameFile
+        }
+    } else if meta.len() == owner_meta.len()
+        && let (Ok(content), Ok(owner_content)) = (fs::read(file), fs::read(owner_file))
+        && content == owner_content
+    {
+        ShadowKind::IdenticalContent
+    } else {
+        ShadowKind::Different
+    }
+}
+
 /// Holds a directory/file relationship that shadows a file with the
 /// same name for some other directory.
 #[derive(Debug, Clone, PartialEq)]
 struct Shadow {
-    owner_dir: String,
-    file: String,
+    owner_dir: OsString,
+    file: OsString,
+    kind: ShadowKind,
 }
 
 impl Shadow {
-    fn new(owner_dir: String, file: String) -> Self {
-        Self { owner_dir, file }
+    fn new(owner_dir: OsString, file: OsString, kind: ShadowKind) -> Self {
+        Self {
+            owner_dir,
+            file,
+            kind,
+        }
     }
 }
 
-fn get_shadowed(path_str: &str) -> Result<Vec<(String, Vec<Shadow>)>> {
-    let mut all_shadowed = Vec::new();
-    let mut file_to_owner_dir: HashMap<String, String> = HashMap::new();
+/// Shadowed files grouped by the directory containing them.
+type ShadowedDirs = Vec<(OsString, Vec<Shadow>)>;
+
+/// Holds a path directory whose contents could not be listed.
+#[derive(Debug, Clone, PartialEq)]
+struct UnreadableDir {
+    dir: OsString,
+    error: String,
+}
+
+impl UnreadableDir {
+    fn new(dir: OsString, error: anyhow::Error) -> Self {
+        Self {
+            dir,
+            error: format!("{:#}", error),
+        }
+    }
+}
+
+/// Prints a warning on stderr for each directory that was skipped
+/// because its contents could not be listed.
+fn warn_unreadable(unreadable: &[UnreadableDir]) {
+    for u in unreadable {
+        eprintln!("warning: skipping {}: {}", u.dir.display(), u.error);
+    }
+}
+
+fn get_unreadable_dirs(path_str: &OsStr) -> Vec<UnreadableDir> {
+    parse_path(path_str)
+        .into_iter()
+        .filter_map(|dir| match files_in_dir(&dir) {
+            Ok(_) => None,
+            Err(e) => Some(UnreadableDir::new(dir, e)),
+        })
+        .collect()
+}
+
+/// Lists the commands in each directory of the path in search order.
+/// Directories that cannot be read are skipped and returned separately
+/// so one bad directory does not hide the commands in the rest of the path.
+fn list_commands(
+    path_str: &OsStr,
+    filter: CommandFilter,
+) -> (Vec<(OsString, BTreeSet<OsString>)>, Vec<UnreadableDir>) {
+    let mut listings = Vec::new();
+    let mut unreadable = Vec::new();
     for dir in parse_raw_path(path_str) {
+        match commands_in_dir(&dir, filter) {
+            Ok(files) => listings.push((dir, files)),
+            Err(e) => {
+                if !unreadable.iter().any(|u: &UnreadableDir| u.dir == dir) {
+                    unreadable.push(UnreadableDir::new(dir, e));
+                }
+            }
+        }
+    }
+    (listings, unreadable)
+}
+
+/// Finds the shadowed files in each directory, along with the
+/// directories that could not be read.
+fn get_shadowed(path_str: &OsStr, filter: CommandFilter) -> (ShadowedDirs, Vec<UnreadableDir>) {
+    let (listings, unreadable) = list_commands(path_str, filter);
+    let mut all_shadowed = Vec::new();
+    let mut file_to_owner_dir: HashMap<OsString, OsString> = HashMap::new();
+    for (dir, files) in listings {
         let mut shadowed = Vec::new();
-        for file in files_in_dir(dir.as_str())? {
-            match file_to_owner_dir.get(file.as_str()) {
+        for file in files {
+            match file_to_owner_dir.get(&file) {
                 Some(owner_dir) => {
-                    shadowed.push(Shadow::new(owner_dir.to_string(), file));
+                    let kind = classify_shadow(
+                        &Path::new(&dir).join(&file),
+                        &Path::new(owner_dir).join(&file),
+                    );
+                    shadowed.push(Shadow::new(owner_dir.to_os_string(), file, kind));
                 }
                 None => {
-                    file_to_owner_dir.insert(file, dir.to_string());
+                    file_to_owner_dir.insert(file, dir.to_os_string());
                 }
             }
         }
@@ -328,5 +1970,397 @@ fn get_shadowed(path_str: &str) -> Result<Vec<(String, Vec<Shadow>)>> {
             all_shadowed.push((dir, shadowed));
         }
     }
-    Ok(all_shadowed)
+    (all_shadowed, unreadable)
+}
+
+/// Holds how much a directory contributes to the commands in the path.
+#[derive(Debug, Clone, PartialEq)]
+struct DirStats {
+    dir: OsString,
+    /// Commands in the directory
+    commands: usize,
+    /// Commands in the directory that are not shadowed
+    reachable: usize,
+    /// Commands in later directories shadowed by this one
+    shadows: usize,
+    /// Commands in the directory shadowed by earlier ones
+    shadowed: usize,
+}
+
+/// Counts the commands in each path entry and how they shadow each
+/// other, with the same ordering as get_shadowed.  Directories that
+/// cannot be read are left out.
+fn get_dir_stats(path_str: &OsStr, filter: CommandFilter) -> Vec<DirStats> {
+    let (listings, _) = list_commands(path_str, filter);
+    let mut stats: Vec<DirStats> = Vec::new();
+    let mut file_to_owner: HashMap<&OsString, usize> = HashMap::new();
+    for (i, (dir, files)) in listings.iter().enumerate() {
+        let mut shadowed = 0;
+        for file in files {
+            match file_to_owner.get(file) {
+                Some(&owner) => {
+                    stats[owner].shadows += 1;
+                    shadowed += 1;
+                }
+                None => {
+                    file_to_owner.insert(file, i);
+                }
+            }
+        }
+        stats.push(DirStats {
+            dir: dir.to_os_string(),
+            commands: files.len(),
+            reachable: files.len() - shadowed,
+            shadows: 0,
+            shadowed,
+        });
+    }
+    stats
+}
+
+/// Holds a directory in the path that contains a command.  Directories
+/// after the first have the kind of shadow the first one causes.
+#[derive(Debug, Clone, PartialEq)]
+struct Candidate {
+    dir: OsString,
+    kind: Option<ShadowKind>,
+}
+
+impl Candidate {
+    fn new(dir: OsString, kind: Option<ShadowKind>) -> Self {
+        Self { dir, kind }
+    }
+}
+
+/// What a search for a command found in one path entry.
+#[derive(Debug, Clone, PartialEq)]
+enum Lookup {
+    /// The entry is not a directory that can be searched
+    Invalid(InvalidReason),
+    /// The directory has no file with the name
+    NoFile,
+    /// The directory exists but the current user cannot search it
+    Unsearchable,
+    /// The name is a symbolic link that does not lead anywhere
+    BrokenSymlink,
+    /// The name is something other than a file, such as a directory
+    NotFile,
+    /// The file cannot be executed by the current user
+    NotExecutable,
+    /// The file is a symbolic link and symbolic links are ignored
+    SymlinkIgnored,
+    /// The command that runs, with every symbolic link followed to reach it
+    Runs(Vec<PathBuf>),
+    /// A command that never runs because of the one before it
+    Shadowed(ShadowKind),
+}
+
+impl Lookup {
+    fn describe(&self, name: &OsStr) -> String {
+        let name = name.display();
+        match self {
+            Lookup::Invalid(reason) => reason.describe().to_string(),
+            Lookup::NoFile => format!("no {name}"),
+            Lookup::Unsearchable => "cannot be searched (permission denied)".to_string(),
+            Lookup::BrokenSymlink => format!("{name} is a broken symlink"),
+            Lookup::NotFile => format!("{name} is not a file"),
+            Lookup::NotExecutable => format!("{name} is not executable"),
+            Lookup::SymlinkIgnored => format!("{name} is a symlink, ignored by --no-symlinks"),
+            Lookup::Runs(_) => format!("{name} runs"),
+            Lookup::Shadowed(kind) => format!("{name} is shadowed ({})", kind.describe()),
+        }
+    }
+}
+
+/// Describes what each entry in the path contributes to finding a
+/// command, in search order.  As in get_shadowed the first command
+/// found runs and shadows the rest, including repeats of the same
+/// directory.  Names containing a slash are never looked up in the
+/// path, as in the shell.
+fn explain_command(
+    path_str: &OsStr,
+    name: &OsStr,
+    filter: CommandFilter,
+) -> Vec<(OsString, Lookup)> {
+    let mut steps = Vec::new();
+    if name.as_bytes().contains(&b'/') {
+        return steps;
+    }
+    let mut owner_file: Option<PathBuf> = None;
+    for dir in parse_raw_path(path_str) {
+        let file = Path::new(&dir).join(name);
+        let lookup = if let Some(reason) = get_invalid_reason(&dir) {
+            Lookup::Invalid(reason)
+        } else if file.is_file() && filter.accepts(&file) {
+            match &owner_file {
+                Some(owner_file) => Lookup::Shadowed(classify_shadow(&file, owner_file)),
+                None => {
+                    let (chain, _) = symlink_chain(&file);
+                    owner_file = Some(file);
+                    Lookup::Runs(chain)
+                }
+            }
+        } else if file.is_file() && filter.no_symlinks && file.is_symlink() {
+            Lookup::SymlinkIgnored
+        } else if file.is_file() {
+            Lookup::NotExecutable
+        } else if file.exists() {
+            Lookup::NotFile
+        } else {
+            match fs::symlink_metadata(&file) {
+                Ok(_) => Lookup::BrokenSymlink,
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Lookup::Unsearchable,
+                Err(_) => Lookup::NoFile,
+            }
+        };
+        steps.push((dir, lookup));
+    }
+    steps
+}
+
+/// Finds every directory in the path with a command of the given name,
+/// in search order, using the same rules as explain_command.
+fn find_command(path_str: &OsStr, name: &OsStr, filter: CommandFilter) -> Vec<Candidate> {
+    explain_command(path_str, name, filter)
+        .into_iter()
+        .filter_map(|(dir, lookup)| match lookup {
+            Lookup::Runs(_) => Some(Candidate::new(dir, None)),
+            Lookup::Shadowed(kind) => Some(Candidate::new(dir, Some(kind))),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Maps each command name to the first directory in the path
+/// that contains it, i.e. the directory the shell would use.
+/// Directories that cannot be read are skipped like the shell does
+/// and returned separately so callers can warn about them.
+fn get_commands(
+    path: &[OsString],
+    filter: CommandFilter,
+) -> (BTreeMap<OsString, OsString>, Vec<UnreadableDir>) {
+    let mut commands = BTreeMap::new();
+    let mut unreadable = Vec::new();
+    for dir in path {
+        match commands_in_dir(dir, filter) {
+            Ok(files) => {
+                for file in files {
+                    commands.entry(file).or_insert_with(|| dir.to_os_string());
+                }
+            }
+            Err(e) => unreadable.push(UnreadableDir::new(dir.to_os_string(), e)),
+        }
+    }
+    (commands, unreadable)
+}
+
+/// Holds a command whose resolving directory differs between two paths.
+/// A missing directory means the command does not resolve in that path.
+#[derive(Debug, Clone, PartialEq)]
+struct CommandChange {
+    command: OsString,
+    old_dir: Option<OsString>,
+    new_dir: Option<OsString>,
+}
+
+impl CommandChange {
+    fn new(command: OsString, old_dir: Option<OsString>, new_dir: Option<OsString>) -> Self {
+        Self {
+            command,
+            old_dir,
+            new_dir,
+        }
+    }
+}
+
+fn get_impact(
+    current: &[OsString],
+    proposed: &[OsString],
+    filter: CommandFilter,
+) -> Vec<CommandChange> {
+    let (mut old_commands, old_unreadable) = get_commands(current, filter);
+    let (mut new_commands, new_unreadable) = get_commands(proposed, filter);
+    warn_unreadable(&old_unreadable);
+    warn_unreadable(
+        &new_unreadable
+            .into_iter()
+            .filter(|u| !old_unreadable.contains(u))
+            .collect::<Vec<_>>(),
+    );
+    let names = old_commands
+        .keys()
+        .chain(new_commands.keys())
+        .cloned()
+        .collect::<BTreeSet<OsString>>();
+    names
+        .into_iter()
+        .filter_map(|command| {
+            let old_dir = old_commands.remove(&command);
+            let new_dir = new_commands.remove(&command);
+            if old_dir == new_dir {
+                None
+            } else {
+                Some(CommandChange::new(command, old_dir, new_dir))
+            }
+        })
+        .collect()
+}
+
+/// Holds the problems found while analyzing a path.
+/// Sections that were not run are left empty.
+#[derive(Debug, Default, PartialEq)]
+struct Analysis {
+    env: String,
+    source: PathSource,
+    sections: Vec<Section>,
+    invalid: Vec<InvalidDir>,
+    duplicates: Vec<OsString>,
+    shadows: ShadowedDirs,
+    benign: BenignShadows,
+    relative: Vec<RelativeDir>,
+    permissions: Vec<InsecureDir>,
+    hijack: Vec<HijackRisk>,
+    executables: Vec<(OsString, Vec<FileProblem>)>,
+    symlinks: Vec<(OsString, Vec<BrokenSymlink>)>,
+    aliases: Vec<AliasGroup>,
+    unreadable: Vec<UnreadableDir>,
+    stats: Option<Vec<DirStats>>,
+}
+
+impl Analysis {
+    fn new(path_str: &OsStr, sections: &[Section], filter: CommandFilter) -> Result<Self> {
+        let mut analysis = Self {
+            sections: sections.to_vec(),
+            ..Self::default()
+        };
+        for section in sections {
+            match section {
+                Section::Invalid => analysis.invalid = get_invalid_dirs(path_str),
+                Section::Duplicates => analysis.duplicates = get_duplicate_dirs(path_str),
+                Section::Shadows => {
+                    (analysis.shadows, analysis.unreadable) = get_shadowed(path_str, filter);
+                }
+                Section::Relative => analysis.relative = get_relative_dirs(path_str),
+                Section::Permissions => analysis.permissions = get_insecure_dirs(path_str),
+                Section::Hijack => analysis.hijack = get_hijack_risks(path_str, filter)?,
+                Section::Executables => analysis.executables = get_file_problems(path_str)?,
+                Section::Symlinks => analysis.symlinks = get_broken_symlinks(path_str),
+                Section::Aliases => analysis.aliases = get_alias_groups(path_str),
+                // The shadow scan already recorded the directories it could not read.
+                Section::Unreadable if sections.contains(&Section::Shadows) => (),
+                Section::Unreadable => analysis.unreadable = get_unreadable_dirs(path_str),
+            }
+        }
+        if !sections.contains(&Section::Unreadable) {
+            analysis.unreadable.clear();
+        }
+        Ok(analysis)
+    }
+
+    /// Records which variable the path was read from and how.
+    fn set_source(&mut self, env: &str, source: PathSource) {
+        self.env = env.to_string();
+        self.source = source;
+    }
+
+    /// Explains an unset, empty or defaulted path variable.
+    fn source_note(&self) -> Option<String> {
+        self.source.describe(&self.env)
+    }
+
+    /// Records how benign shadows are reported, removing them if hidden.
+    fn set_benign(&mut self, benign: BenignShadows) {
+        self.benign = benign;
+        if benign == BenignShadows::Hide {
+            self.shadows.retain_mut(|(_, dir_shadows)| {
+                dir_shadows.retain(|s| !s.kind.is_benign());
+                !dir_shadows.is_empty()
+            });
+        }
+    }
+
+    /// Returns the shadows to report with a title for each group.
+    /// Grouping moves benign shadows into a second group.
+    fn shadow_groups(&self) -> Vec<(&'static str, ShadowedDirs)> {
+        let title = Section::Shadows.title();
+        if self.benign != BenignShadows::Group {
+            return vec![(title, self.shadows.clone())];
+        }
+        let select = |benign: bool| {
+            self.shadows
+                .iter()
+                .map(|(dir, dir_shadows)| {
+                    let selected = dir_shadows
+                        .iter()
+                        .filter(|s| s.kind.is_benign() == benign)
+                        .cloned()
+                        .collect::<Vec<Shadow>>();
+                    (dir.to_os_string(), selected)
+                })
+                .filter(|(_, selected)| !selected.is_empty())
+                .collect::<ShadowedDirs>()
+        };
+        vec![
+            (title, select(false)),
+            ("Benign Shadowed Files", select(true)),
+        ]
+    }
+
+    /// Removes any problems whose directory, file name, or
+    /// file path appears in the ignored list.
+    fn ignore(&mut self, ignored: &[OsString]) {
+        let is_ignored = |s: &OsStr| ignored.iter().any(|x| x == s);
+        let is_file_ignored = |dir: &OsStr, file: &OsStr| {
+            is_ignored(file) || is_ignored(&[dir, file].join(OsStr::new("/")))
+        };
+        self.invalid.retain(|i| !is_ignored(&i.dir));
+        self.duplicates.retain(|d| !is_ignored(d));
+        self.shadows.retain_mut(|(dir, dir_shadows)| {
+            dir_shadows.retain(|s| !is_file_ignored(dir, &s.file));
+            !(is_ignored(dir) || dir_shadows.is_empty())
+        });
+        self.relative.retain(|r| !is_ignored(&r.dir));
+        self.permissions.retain(|p| !is_ignored(&p.dir));
+        self.hijack.retain_mut(|risk| {
+            risk.commands
+                .retain(|(command, dir)| !is_file_ignored(dir, command));
+            !(is_ignored(&risk.dir) || risk.commands.is_empty())
+        });
+        self.executables.retain_mut(|(dir, problems)| {
+            problems.retain(|p| !is_file_ignored(dir, &p.file));
+            !(is_ignored(dir) || problems.is_empty())
+        });
+        self.symlinks.retain_mut(|(dir, symlinks)| {
+            symlinks.retain(|s| !is_file_ignored(dir, &s.file));
+            !(is_ignored(dir) || symlinks.is_empty())
+        });
+        self.aliases
+            .retain(|group| !group.dirs.iter().any(|d| is_ignored(d)));
+        self.unreadable.retain(|u| !is_ignored(&u.dir));
+    }
+
+    /// Returns the number of problems found in a section.
+    fn count(&self, section: Section) -> usize {
+        match section {
+            Section::Invalid => self.invalid.len(),
+            Section::Duplicates => self.duplicates.len(),
+            Section::Shadows => self.shadows.iter().map(|(_, s)| s.len()).sum(),
+            Section::Relative => self.relative.len(),
+            Section::Permissions => self.permissions.len(),
+            Section::Hijack => self.hijack.len(),
+            Section::Executables => self.executables.iter().map(|(_, p)| p.len()).sum(),
+            Section::Symlinks => self.symlinks.iter().map(|(_, s)| s.len()).sum(),
+            Section::Aliases => self.aliases.len(),
+            Section::Unreadable => self.unreadable.len(),
+        }
+    }
+
+    /// Returns the first of the given sections, in report order,
+    /// that contains any problems.
+    fn failed_section(&self, fail_on: &[Section]) -> Option<Section> {
+        Section::value_variants()
+            .iter()
+            .copied()
+            .find(|s| fail_on.contains(s) && self.count(*s) > 0)
+    }
 }
@@ -0,0 +1,735 @@
+// Utility to edit, filter, and print unix PATH-like strings.
+// Copyright (C) 2025  Brian Burton
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{Analysis, FileProblemKind, PathSource, Section};
+use anyhow::Result;
+use itertools::Itertools;
+use serde_json::{Value, json};
+use std::io::Write;
+
+/// Severity of a finding, using the same levels as SARIF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    None,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::None => "none",
+        }
+    }
+}
+
+/// Identifies the kind of problem a finding reports.
+/// Rule ids are part of the output format and must never be reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    InvalidDir,
+    DuplicateDir,
+    ShadowedFile,
+    RelativeDir,
+    InsecureDir,
+    HijackRisk,
+    NonExecutableFile,
+    WritableExecutable,
+    SetIdInWritableDir,
+    BenignShadow,
+    BrokenSymlink,
+    AliasDir,
+    UnreadableDir,
+    EmptyPath,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 14] = [
+        Rule::InvalidDir,
+        Rule::DuplicateDir,
+        Rule::ShadowedFile,
+        Rule::RelativeDir,
+        Rule::InsecureDir,
+        Rule::HijackRisk,
+        Rule::NonExecutableFile,
+        Rule::WritableExecutable,
+        Rule::SetIdInWritableDir,
+        Rule::BenignShadow,
+        Rule::BrokenSymlink,
+        Rule::AliasDir,
+        Rule::UnreadableDir,
+        Rule::EmptyPath,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::InvalidDir => "PT001",
+            Rule::DuplicateDir => "PT002",
+            Rule::ShadowedFile => "PT003",
+            Rule::RelativeDir => "PT004",
+            Rule::InsecureDir => "PT005",
+            Rule::HijackRisk => "PT006",
+            Rule::NonExecutableFile => "PT007",
+            Rule::WritableExecutable => "PT008",
+            Rule::SetIdInWritableDir => "PT009",
+            Rule::BenignShadow => "PT010",
+            Rule::BrokenSymlink => "PT011",
+            Rule::AliasDir => "PT012",
+            Rule::UnreadableDir => "PT013",
+            Rule::EmptyPath => "PT014",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::InvalidDir => "invalid-dir",
+            Rule::DuplicateDir => "duplicate-dir",
+            Rule::ShadowedFile => "shadowed-file",
+            Rule::RelativeDir => "relative-dir",
+            Rule::InsecureDir => "insecure-dir",
+            Rule::HijackRisk => "hijack-risk",
+            Rule::NonExecutableFile => "non-executable-file",
+            Rule::WritableExecutable => "writable-executable",
+            Rule::SetIdInWritableDir => "setid-in-writable-dir",
+            Rule::BenignShadow => "benign-shadowed-file",
+            Rule::BrokenSymlink => "broken-symlink",
+            Rule::AliasDir => "alias-dir",
+            Rule::UnreadableDir => "unreadable-dir",
+            Rule::EmptyPath => "empty-path",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Rule::InvalidDir => "Directory in the path does not exist or is not a directory",
+            Rule::DuplicateDir => "Directory appears more than once in the path",
+            Rule::ShadowedFile => "File is hidden by a file with the same name earlier in the path",
+            Rule::RelativeDir => "Path entry is relative, empty, or contains . or .. components",
+            Rule::InsecureDir => "Directory can be modified by users other than the owner or root",
+            Rule::HijackRisk => {
+                "Writable directory precedes root-owned directories whose commands it can shadow"
+            }
+            Rule::NonExecutableFile => "File in a path directory is not executable",
+            Rule::WritableExecutable => "Executable can be modified by users other than the owner",
+            Rule::SetIdInWritableDir => "Setuid or setgid executable is in a writable directory",
+            Rule::BenignShadow => "File is shadowed by the same file or one with identical content",
+            Rule::BrokenSymlink => "Symbolic link in a path directory does not lead to a file",
+            Rule::AliasDir => "Directory is another name for a directory earlier in the path",
+            Rule::UnreadableDir => "Directory in the path cannot be listed",
+            Rule::EmptyPath => "Path environment variable is unset or empty",
+        }
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            Rule::InvalidDir
+            | Rule::DuplicateDir
+            | Rule::BrokenSymlink
+            | Rule::AliasDir
+            | Rule::UnreadableDir
+            | Rule::EmptyPath => Severity::Warning,
+            Rule::ShadowedFile => Severity::Note,
+            Rule::NonExecutableFile => Severity::Note,
+            Rule::BenignShadow => Severity::None,
+            Rule::RelativeDir
+            | Rule::InsecureDir
+            | Rule::HijackRisk
+            | Rule::WritableExecutable
+            | Rule::SetIdInWritableDir => Severity::Error,
+        }
+    }
+}
+
+/// A single problem found in a path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub rule: Rule,
+    pub directory: String,
+    pub details: String,
+    pub suggestion: Option<String>,
+    pub variable: Option<String>,
+}
+
+impl Finding {
+    pub fn new(rule: Rule, directory: String, details: String) -> Self {
+        Self {
+            rule,
+            directory,
+            details,
+            suggestion: None,
+            variable: None,
+        }
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Option<String>) -> Self {
+        self.suggestion = suggestion;
+        self
+    }
+
+    /// Names the environment variable for findings about the path as a whole.
+    pub fn with_variable(mut self, variable: Option<String>) -> Self {
+        self.variable = variable;
+        self
+    }
+
+    fn to_json(&self) -> Value {
+        let mut value = json!({
+            "rule": self.rule.id(),
+            "name": self.rule.name(),
+            "severity": self.rule.severity().as_str(),
+            "directory": self.directory,
+            "details": self.details,
+        });
+        if let Some(suggestion) = &self.suggestion {
+            value["suggestion"] = json!(suggestion);
+        }
+        if let Some(variable) = &self.variable {
+            value["variable"] = json!(variable);
+        }
+        value
+    }
+
+    /// Empty entries are shown as a pair of quotes so they remain visible,
+    /// as in the text report.
+    fn display_dir(&self) -> String {
+        if self.directory.is_empty() {
+            "\"\"".to_string()
+        } else {
+            self.directory.clone()
+        }
+    }
+
+    /// Details followed by the suggestion, for formats without a separate field.
+    fn message(&self) -> String {
+        match &self.suggestion {
+            Some(suggestion) => format!("{}, did you mean {}?", self.details, suggestion),
+            None => self.details.to_string(),
+        }
+    }
+}
+
+/// Converts the sections of an analysis into findings in report order,
+/// preceded by a finding for an unset or empty path variable.
+pub fn findings(analysis: &Analysis) -> Vec<Finding> {
+    let source = match analysis.source {
+        PathSource::Empty | PathSource::Unset => analysis.source_note(),
+        PathSource::Set | PathSource::Default => None,
+    };
+    source
+        .map(|note| {
+            Finding::new(Rule::EmptyPath, String::new(), note)
+                .with_variable(Some(analysis.env.clone()))
+        })
+        .into_iter()
+        .chain(
+            analysis
+                .sections
+                .iter()
+                .flat_map(|section| section_findings(analysis, *section)),
+        )
+        .collect()
+}
+
+fn section_findings(analysis: &Analysis, section: Section) -> Vec<Finding> {
+    match section {
+        Section::Invalid => analysis
+            .invalid
+            .iter()
+            .map(|i| {
+                Finding::new(
+                    Rule::InvalidDir,
+                    i.dir.display().to_string(),
+                    i.reason.describe().to_string(),
+                )
+                .with_suggestion(i.suggestion.as_ref().map(|s| s.display().to_string()))
+            })
+            .collect(),
+        Section::Duplicates => analysis
+            .duplicates
+            .iter()
+            .map(|dir| {
+                Finding::new(
+                    Rule::DuplicateDir,
+                    dir.display().to_string(),
+                    "appears earlier in the path".to_string(),
+                )
+            })
+            .collect(),
+        Section::Shadows => analysis
+            .shadows
+            .iter()
+            .flat_map(|(dir, dir_shadows)| {
+                dir_shadows.iter().map(|s| {
+                    if s.kind.is_benign() {
+                        Finding::new(
+                            Rule::BenignShadow,
+                            dir.display().to_string(),
+                            format!(
+                                "{} is shadowed by {} ({})",
+                                s.file.display(),
+                                s.owner_dir.display(),
+                                s.kind.describe()
+                            ),
+                        )
+                    } else {
+                        Finding::new(
+                            Rule::ShadowedFile,
+                            dir.display().to_string(),
+                            format!(
+                                "{} is shadowed by {}",
+                                s.file.display(),
+                                s.owner_dir.display()
+                            ),
+                        )
+                    }
+                })
+            })
+            .collect(),
+        Section::Relative => analysis
+            .relative
+            .iter()
+            .map(|r| {
+                Finding::new(
+                    Rule::RelativeDir,
+                    r.dir.display().to_string(),
+                    r.reason.describe().to_string(),
+                )
+            })
+            .collect(),
+        Section::Permissions => analysis
+            .permissions
+            .iter()
+            .map(|p| {
+                Finding::new(
+                    Rule::InsecureDir,
+                    p.dir.display().to_string(),
+                    p.problem.describe(),
+                )
+            })
+            .collect(),
+        Section::Hijack => analysis
+            .hijack
+            .iter()
+            .map(|risk| {
+                Finding::new(
+                    Rule::HijackRisk,
+                    risk.dir.display().to_string(),
+                    format!(
+                        "{} and can shadow {} commands from {}",
+                        risk.describe_writers(),
+                        risk.commands.len(),
+                        risk.commands
+                            .iter()
+                            .map(|(_, d)| d.display())
+                            .unique_by(|d| d.to_string())
+                            .join(", ")
+                    ),
+                )
+            })
+            .collect(),
+        Section::Executables => analysis
+            .executables
+            .iter()
+            .flat_map(|(dir, problems)| {
+                problems.iter().map(|p| {
+                    let rule = match p.kind {
+                        FileProblemKind::NotExecutable => Rule::NonExecutableFile,
+                        FileProblemKind::WritableByOthers => Rule::WritableExecutable,
+                        FileProblemKind::SetIdInWritableDir => Rule::SetIdInWritableDir,
+                    };
+                    Finding::new(
+                        rule,
+                        dir.display().to_string(),
+                        format!("{} is {}", p.file.display(), p.kind.describe()),
+                    )
+                })
+            })
+            .collect(),
+        Section::Symlinks => analysis
+            .symlinks
+            .iter()
+            .flat_map(|(dir, symlinks)| {
+                symlinks.iter().map(|s| {
+                    Finding::new(Rule::BrokenSymlink, dir.display().to_string(), s.describe())
+                })
+            })
+            .collect(),
+        Section::Aliases => analysis
+            .aliases
+            .iter()
+            .flat_map(|group| {
+                group.dirs.iter().skip(1).map(|dir| {
+                    Finding::new(
+                        Rule::AliasDir,
+                        dir.display().to_string(),
+                        format!(
+                            "{} is searched first and both name {} ({})",
+                            group.dirs[0].display(),
+                            group.target.display(),
+                            group.kind.describe()
+                        ),
+                    )
+                })
+            })
+            .collect(),
+        Section::Unreadable => analysis
+            .unreadable
+            .iter()
+            .map(|u| {
+                Finding::new(
+                    Rule::UnreadableDir,
+                    u.dir.display().to_string(),
+                    u.error.to_string(),
+                )
+            })
+            .collect(),
+    }
+}
+
+/// Writes one JSON object per finding per line.
+pub fn write_json_lines(output: &mut impl Write, findings: &[Finding]) -> Result<()> {
+    for finding in findings {
+        writeln!(output, "{}", finding.to_json())?;
+    }
+    Ok(())
+}
+
+/// Writes all findings as a single SARIF 2.1.0 log.
+pub fn write_sarif(output: &mut impl Write, findings: &[Finding]) -> Result<()> {
+    let rules = Rule::ALL
+        .iter()
+        .map(|r| {
+            json!({
+                "id": r.id(),
+                "name": r.name(),
+                "shortDescription": { "text": r.description() },
+                "defaultConfiguration": { "level": r.severity().as_str() },
+            })
+        })
+        .collect::<Vec<Value>>();
+    let results = findings
+        .iter()
+        .map(|f| {
+            let mut result = json!({
+                "ruleId": f.rule.id(),
+                "level": f.rule.severity().as_str(),
+                "message": { "text": f.message() },
+            });
+            if let Some(location) = sarif_location(f) {
+                result["locations"] = json!([location]);
+            }
+            result
+        })
+        .collect::<Vec<Value>>();
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }],
+    });
+    serde_json::to_writer_pretty(&mut *output, &log)?;
+    writeln!(output)?;
+    Ok(())
+}
+
+/// Returns the SARIF location of a finding.  Absolute directories get a
+/// `file://` URI while relative entries, which have no fixed place on disk,
+/// only get a logical name.  Empty entries and the empty path have neither.
+fn sarif_location(finding: &Finding) -> Option<Value> {
+    if finding.rule == Rule::EmptyPath || finding.directory.is_empty() {
+        None
+    } else if finding.directory.starts_with('/') {
+        Some(json!({
+            "physicalLocation": {
+                "artifactLocation": { "uri": file_uri(&finding.directory) }
+            }
+        }))
+    } else {
+        Some(json!({
+            "logicalLocations": [{ "name": finding.directory }]
+        }))
+    }
+}
+
+/// Formats an absolute path as a `file://` URI, percent-encoding every
+/// byte other than `/` and the unreserved characters of RFC 3986.
+fn file_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for &b in path.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Formats text as a markdown code span, which needs no other escaping.
+fn md_code(s: &str) -> String {
+    if s.contains('`') {
+        format!("`` {} ``", s)
+    } else {
+        format!("`{}`", s)
+    }
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.75em; text-align: left; }
+summary { cursor: pointer; margin: 0.25em 0; }
+details table { margin: 0.5em 0 1em 1.5em; }";
+
+/// Writes the analysis as a standalone HTML page with a summary table
+/// and one collapsible list of shadowed files per directory.
+pub fn write_html(output: &mut impl Write, analysis: &Analysis) -> Result<()> {
+    writeln!(output, "<!DOCTYPE html>")?;
+    writeln!(output, "<html lang=\"en\">")?;
+    writeln!(output, "<head>")?;
+    writeln!(output, "<meta charset=\"utf-8\">")?;
+    writeln!(output, "<title>Path Analysis</title>")?;
+    writeln!(output, "<style>\n{}\n</style>", HTML_STYLE)?;
+    writeln!(output, "</head>")?;
+    writeln!(output, "<body>")?;
+    writeln!(output, "<h1>Path Analysis</h1>")?;
+    if let Some(note) = analysis.source_note() {
+        writeln!(output, "<p>{}</p>", escape_html(&note))?;
+    }
+
+    writeln!(output, "<h2>Summary</h2>")?;
+    writeln!(output, "<table>")?;
+    writeln!(output, "<tr><th>Section</th><th>Problems</th></tr>")?;
+    for section in &analysis.sections {
+        writeln!(
+            output,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            section.title(),
+            analysis.count(*section)
+        )?;
+    }
+    writeln!(output, "</table>")?;
+
+    for section in &analysis.sections {
+        writeln!(output, "<h2>{}</h2>", section.title())?;
+        if analysis.count(*section) == 0 {
+            writeln!(output, "<p>None</p>")?;
+            continue;
+        }
+        match section {
+            Section::Shadows => {
+                for (i, (title, shadows)) in analysis.shadow_groups().iter().enumerate() {
+                    if i > 0 {
+                        writeln!(output, "<h3>{}</h3>", title)?;
+                    }
+                    if shadows.is_empty() {
+                        writeln!(output, "<p>None</p>")?;
+                    }
+                    for (dir, dir_shadows) in shadows {
+                        writeln!(output, "<details>")?;
+                        writeln!(
+                            output,
+                            "<summary><code>{}</code> ({} shadowed)</summary>",
+                            escape_html(&dir.display().to_string()),
+                            dir_shadows.len()
+                        )?;
+                        writeln!(output, "<table>")?;
+                        writeln!(
+                            output,
+                            "<tr><th>File</th><th>Shadowed By</th><th>Kind</th></tr>"
+                        )?;
+                        for s in dir_shadows {
+                            writeln!(
+                                output,
+                                "<tr><td><code>{}</code></td><td><code>{}</code></td><td>{}</td></tr>",
+                                escape_html(&s.file.display().to_string()),
+                                escape_html(&s.owner_dir.display().to_string()),
+                                s.kind.describe()
+                            )?;
+                        }
+                        writeln!(output, "</table>")?;
+                        writeln!(output, "</details>")?;
+                    }
+                }
+            }
+            _ => {
+                writeln!(output, "<ul>")?;
+                for finding in section_findings(analysis, *section) {
+                    writeln!(
+                        output,
+                        "<li><code>{}</code>: {}</li>",
+                        escape_html(&finding.display_dir()),
+                        escape_html(&finding.message())
+                    )?;
+                }
+                writeln!(output, "</ul>")?;
+            }
+        }
+    }
+
+    if let Some(stats) = &analysis.stats {
+        writeln!(output, "<h2>Directory Statistics</h2>")?;
+        writeln!(output, "<table>")?;
+        writeln!(
+            output,
+            "<tr><th>Directory</th><th>Commands</th><th>Reachable</th><th>Shadows</th><th>Shadowed</th></tr>"
+        )?;
+        for s in stats {
+            writeln!(
+                output,
+                "<tr><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&s.dir.display().to_string()),
+                s.commands,
+                s.reachable,
+                s.shadows,
+                s.shadowed
+            )?;
+        }
+        writeln!(output, "</table>")?;
+    }
+
+    writeln!(output, "</body>")?;
+    writeln!(output, "</html>")?;
+    Ok(())
+}
+
+/// Writes the analysis as a markdown document.  Shadowed files use
+/// HTML details elements so that renderers that support them collapse
+/// each directory.
+pub fn write_markdown(output: &mut impl Write, analysis: &Analysis) -> Result<()> {
+    writeln!(output, "# Path Analysis")?;
+    writeln!(output)?;
+    if let Some(note) = analysis.source_note() {
+        writeln!(output, "{note}")?;
+        writeln!(output)?;
+    }
+    writeln!(output, "## Summary")?;
+    writeln!(output)?;
+    writeln!(output, "| Section | Problems |")?;
+    writeln!(output, "|---------|----------|")?;
+    for section in &analysis.sections {
+        writeln!(
+            output,
+            "| {} | {} |",
+            section.title(),
+            analysis.count(*section)
+        )?;
+    }
+
+    for section in &analysis.sections {
+        writeln!(output)?;
+        writeln!(output, "## {}", section.title())?;
+        writeln!(output)?;
+        if analysis.count(*section) == 0 {
+            writeln!(output, "None")?;
+            continue;
+        }
+        match section {
+            Section::Shadows => {
+                for (i, (title, shadows)) in analysis.shadow_groups().iter().enumerate() {
+                    if i > 0 {
+                        writeln!(output)?;
+                        writeln!(output, "### {}", title)?;
+                        writeln!(output)?;
+                    }
+                    if shadows.is_empty() {
+                        writeln!(output, "None")?;
+                    }
+                    for (j, (dir, dir_shadows)) in shadows.iter().enumerate() {
+                        if j > 0 {
+                            writeln!(output)?;
+                        }
+                        writeln!(output, "<details>")?;
+                        writeln!(
+                            output,
+                            "<summary><code>{}</code> ({} shadowed)</summary>",
+                            escape_html(&dir.display().to_string()),
+                            dir_shadows.len()
+                        )?;
+                        writeln!(output)?;
+                        writeln!(output, "| File | Shadowed By | Kind |")?;
+                        writeln!(output, "|------|-------------|------|")?;
+                        for s in dir_shadows {
+                            writeln!(
+                                output,
+                                "| {} | {} | {} |",
+                                md_code(&s.file.display().to_string()).replace('|', "\\|"),
+                                md_code(&s.owner_dir.display().to_string()).replace('|', "\\|"),
+                                s.kind.describe()
+                            )?;
+                        }
+                        writeln!(output)?;
+                        writeln!(output, "</details>")?;
+                    }
+                }
+            }
+            _ => {
+                for finding in section_findings(analysis, *section) {
+                    writeln!(
+                        output,
+                        "- {}: {}",
+                        md_code(&finding.display_dir()),
+                        finding.message()
+                    )?;
+                }
+            }
+        }
+    }
+
+    if let Some(stats) = &analysis.stats {
+        writeln!(output)?;
+        writeln!(output, "## Directory Statistics")?;
+        writeln!(output)?;
+        writeln!(
+            output,
+            "| Directory | Commands | Reachable | Shadows | Shadowed |"
+        )?;
+        writeln!(
+            output,
+            "|-----------|----------|-----------|---------|----------|"
+        )?;
+        for s in stats {
+            writeln!(
+                output,
+                "| {} | {} | {} | {} | {} |",
+                md_code(&s.dir.display().to_string()).replace('|', "\\|"),
+                s.commands,
+                s.reachable,
+                s.shadows,
+                s.shadowed
+            )?;
+        }
+    }
+    Ok(())
+}
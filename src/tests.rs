@@ -14,20 +14,37 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+#![allow(
+    clippy::len_zero,
+    clippy::redundant_closure,
+    clippy::unnecessary_to_owned,
+    clippy::useless_vec
+)]
+
 use super::*;
+use report::{Finding, Rule};
 use std::env::set_var;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
 
 const TEST_ROOT: &str = "test_dirs";
 
-fn dir(s: &str) -> String {
-    format!("{}/{}", TEST_ROOT, s)
+// Counts every file in the test directories as a command, as
+// older versions did, since most fixtures are not executable.
+const ALL_FILES: CommandFilter = CommandFilter {
+    all_files: true,
+    no_symlinks: false,
+};
+
+fn dir(s: &str) -> OsString {
+    OsString::from(format!("{}/{}", TEST_ROOT, s))
 }
 
 fn err_message(e: anyhow::Error) -> String {
     format!("{:?}", e)
 }
 
-fn normal_dir(s: &str) -> String {
+fn normal_dir(s: &str) -> OsString {
     let mut prefix = fs::canonicalize(Path::new(TEST_ROOT))
         .unwrap()
         .to_str()
@@ -36,15 +53,16 @@ fn normal_dir(s: &str) -> String {
     assert!(prefix.len() > 0);
     prefix += "/";
     prefix += s;
-    prefix
+    OsString::from(prefix)
 }
 
 // Determines the canonical path of TEST_ROOT and then removes
 // it from the start of the given directory path.
 // Intended for use in a test so makes assumptions about
 // unwrap being safe.
-fn rm_prefix(path: String) -> String {
-    let prefix = normal_dir("");
+fn rm_prefix(path: OsString) -> String {
+    let prefix = normal_dir("").into_string().unwrap();
+    let path = path.into_string().unwrap();
     assert!(
         path.starts_with(prefix.as_str()),
         "path did not start with {}: {}",
@@ -58,12 +76,40 @@ fn rm_prefix(path: String) -> String {
 // it from the start of the given directory path.
 // Intended for use in a test so makes assumptions about
 // unwrap being safe.
-fn rm_prefix_opt(dir: Option<String>) -> Option<String> {
+fn rm_prefix_opt(dir: Option<OsString>) -> Option<String> {
     dir.map(|path| rm_prefix(path))
 }
 
-fn strings(strs: &[&str]) -> Vec<String> {
-    strs.iter().map(|s| String::from(*s)).collect()
+// Creates an empty directory for a test under the system temp directory
+// and returns its path.  Used by tests that need file modes git
+// does not preserve.
+fn temp_dir(name: &str) -> OsString {
+    let path: PathBuf = env::temp_dir().join("path-tool-tests").join(name);
+    if path.exists() {
+        fs::remove_dir_all(&path).unwrap();
+    }
+    fs::create_dir_all(&path).unwrap();
+    path.into_os_string()
+}
+
+fn chmod(path: impl AsRef<Path>, mode: u32) {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+}
+
+fn filter(path: Vec<OsString>) -> Vec<OsString> {
+    apply_filters(path, &[Stage::Exists, Stage::Dedupe], OnError::Fail).unwrap()
+}
+
+fn clean(path: Vec<OsString>) -> Vec<OsString> {
+    apply_filters(path, &[Stage::Clean, Stage::Dedupe], OnError::Fail).unwrap()
+}
+
+fn normalize(path: Vec<OsString>) -> Vec<OsString> {
+    apply_filters(path, &[Stage::Canonicalize, Stage::Dedupe], OnError::Fail).unwrap()
+}
+
+fn strings(strs: &[&str]) -> Vec<OsString> {
+    strs.iter().map(OsString::from).collect()
 }
 
 #[test]
@@ -71,71 +117,65 @@ fn test_remove() {
     let mut v = strings(&["a", "b", "b"]);
     let unchanged = v.clone();
 
-    remove(&mut v, "x");
+    remove(&mut v, OsStr::new("x"));
     assert_eq!(v, unchanged);
 
-    remove(&mut v, "a");
+    remove(&mut v, OsStr::new("a"));
     assert_eq!(v, strings(&["b", "b"]));
 
     let mut v = unchanged.clone();
-    remove(&mut v, "b");
+    remove(&mut v, OsStr::new("b"));
     assert_eq!(v, strings(&["a"]));
 }
 
 #[test]
 fn test_is_valid() {
-    assert_eq!(is_valid(":").map_err(err_message), Ok(false));
-    assert_eq!(is_valid(TEST_ROOT).ok(), Some(true));
-    assert_eq!(is_valid(dir("a").as_str()).ok(), Some(true));
-    assert_eq!(is_valid(dir("b/bb").as_str()).ok(), Some(true));
-    assert_eq!(is_valid(dir("z").as_str()).ok(), Some(false));
-    assert_eq!(is_valid(dir("a/keepme.txt").as_str()).ok(), Some(false));
-    assert_eq!(is_valid(dir("la").as_str()).ok(), Some(true));
-    assert_eq!(is_valid(dir("laa").as_str()).ok(), Some(true));
-    assert_eq!(is_valid(dir("broken").as_str()).ok(), Some(false));
-    assert_eq!(is_valid(dir("broken2").as_str()).ok(), Some(false));
+    assert_eq!(is_valid(OsStr::new(":")).map_err(err_message), Ok(false));
+    assert_eq!(is_valid(OsStr::new(TEST_ROOT)).ok(), Some(true));
+    assert_eq!(is_valid(&dir("a")).ok(), Some(true));
+    assert_eq!(is_valid(&dir("b/bb")).ok(), Some(true));
+    assert_eq!(is_valid(&dir("z")).ok(), Some(false));
+    assert_eq!(is_valid(&dir("a/keepme.txt")).ok(), Some(false));
+    assert_eq!(is_valid(&dir("la")).ok(), Some(true));
+    assert_eq!(is_valid(&dir("laa")).ok(), Some(true));
+    assert_eq!(is_valid(&dir("broken")).ok(), Some(false));
+    assert_eq!(is_valid(&dir("broken2")).ok(), Some(false));
 }
 
 #[test]
 fn test_canonicalize() {
     assert_eq!(
-        canonicalize(dir("a").as_str())
+        canonicalize(&dir("a"))
             .map_err(err_message)
             .map(rm_prefix_opt),
-        Ok(Some("a".to_string()))
+        Ok(Some("a".into()))
     );
     assert_eq!(
-        canonicalize(dir("b").as_str())
+        canonicalize(&dir("b"))
             .map_err(err_message)
             .map(rm_prefix_opt),
-        Ok(Some("b".to_string()))
+        Ok(Some("b".into()))
     );
     assert_eq!(
-        canonicalize(dir("b/bb").as_str())
+        canonicalize(&dir("b/bb"))
             .map_err(err_message)
             .map(rm_prefix_opt),
-        Ok(Some("b/bb".to_string()))
+        Ok(Some("b/bb".into()))
     );
     assert_eq!(
-        canonicalize(dir("la").as_str())
+        canonicalize(&dir("la"))
             .map_err(err_message)
             .map(rm_prefix_opt),
-        Ok(Some("a".to_string()))
+        Ok(Some("a".into()))
     );
     assert_eq!(
-        canonicalize(dir("laa").as_str())
+        canonicalize(&dir("laa"))
             .map_err(err_message)
             .map(rm_prefix_opt),
-        Ok(Some("a".to_string()))
-    );
-    assert_eq!(
-        canonicalize(dir("broken").as_str()).map_err(err_message),
-        Ok(None)
-    );
-    assert_eq!(
-        canonicalize(dir("broken2").as_str()).map_err(err_message),
-        Ok(None)
+        Ok(Some("a".into()))
     );
+    assert_eq!(canonicalize(&dir("broken")).map_err(err_message), Ok(None));
+    assert_eq!(canonicalize(&dir("broken2")).map_err(err_message), Ok(None));
 }
 
 #[test]
@@ -151,7 +191,7 @@ fn test_filter() {
             dir("b")
         ])
         .into_iter()
-        .collect::<Vec<String>>(),
+        .collect::<Vec<OsString>>(),
         vec![dir("laa"), dir("b/bb"), dir("c"), dir("b")]
     );
 }
@@ -204,40 +244,257 @@ fn test_normalize() {
     );
 }
 
+#[test]
+fn test_clean() {
+    assert_eq!(
+        clean(vec![
+            dir("la/"),
+            dir("b//bb"),
+            dir("z/./"),
+            dir("b/bb/../bb"),
+            dir("la"),
+            OsString::from(format!("./{}", dir("c").display()))
+        ]),
+        vec![dir("la"), dir("b/bb"), dir("z"), dir("c")]
+    );
+}
+
+#[test]
+fn test_stages() {
+    let cli = Cli {
+        normalize: true,
+        filter: true,
+        clean: true,
+        pipeline: vec![Stage::Exists],
+        ..Cli::default()
+    };
+    assert_eq!(
+        cli.stages(),
+        vec![
+            Stage::Clean,
+            Stage::Exists,
+            Stage::Canonicalize,
+            Stage::Dedupe,
+            Stage::Exists
+        ]
+    );
+    assert_eq!(Cli::default().stages(), vec![]);
+
+    let path = vec![dir("la/"), dir("z"), dir("a"), dir("la")];
+    assert_eq!(
+        apply_filters(path.clone(), &[Stage::Clean, Stage::Exists], OnError::Fail).unwrap(),
+        vec![dir("la"), dir("a"), dir("la")]
+    );
+    assert_eq!(
+        apply_filters(path.clone(), &[Stage::Clean, Stage::Dedupe], OnError::Fail).unwrap(),
+        vec![dir("la"), dir("z"), dir("a")]
+    );
+    assert_eq!(
+        apply_filters(path, &[Stage::Canonicalize, Stage::Dedupe], OnError::Fail).unwrap(),
+        vec![normal_dir("a")]
+    );
+}
+
+#[test]
+fn test_on_error() {
+    let unreadable = dir(&"x".repeat(300));
+    let path = vec![dir("la"), unreadable.clone(), dir("z")];
+    let stages = [Stage::Canonicalize];
+    assert!(is_valid(&unreadable).is_err());
+    assert_eq!(
+        apply_filters(path.clone(), &stages, OnError::Keep).map_err(err_message),
+        Ok(vec![normal_dir("a"), unreadable.clone()])
+    );
+    assert_eq!(
+        apply_filters(path.clone(), &stages, OnError::Drop).map_err(err_message),
+        Ok(vec![normal_dir("a")])
+    );
+    assert!(
+        apply_filters(path, &stages, OnError::Fail)
+            .map_err(err_message)
+            .unwrap_err()
+            .starts_with(&format!("unable to filter {}", unreadable.display()))
+    );
+
+    let env_var = "TEST_PATH_ON_ERROR".to_string();
+    unsafe {
+        set_var(
+            &env_var,
+            [dir("a"), unreadable.clone()].join(OsStr::new(":")),
+        );
+    }
+    let cli = Cli {
+        env: env_var,
+        filter: true,
+        ..Cli::default()
+    };
+    let mut buf = Vec::new();
+    main_logic(cli, &mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        dir("a").display().to_string() + "\n"
+    );
+}
+
 #[test]
 fn test_add_unique() {
-    let mut path: Vec<String> = Vec::new();
+    let mut path: Vec<OsString> = Vec::new();
 
-    add_unique(&mut path, "");
-    assert_eq!(path, Vec::<String>::new());
+    add_unique(&mut path, OsStr::new(""));
+    assert_eq!(path, Vec::<OsString>::new());
 
-    add_unique(&mut path, "a");
-    add_unique(&mut path, "b");
-    add_unique(&mut path, "c");
+    add_unique(&mut path, OsStr::new("a"));
+    add_unique(&mut path, OsStr::new("b"));
+    add_unique(&mut path, OsStr::new("c"));
     assert_eq!(path, vec!["a", "b", "c"]);
 
-    add_unique(&mut path, "c");
-    add_unique(&mut path, "b");
-    add_unique(&mut path, "a");
+    add_unique(&mut path, OsStr::new("c"));
+    add_unique(&mut path, OsStr::new("b"));
+    add_unique(&mut path, OsStr::new("a"));
     assert_eq!(path, vec!["a", "b", "c"]);
 }
 
 #[test]
 fn test_parse_path() {
-    assert_eq!(parse_path(""), Vec::<String>::new());
-    assert_eq!(parse_path("::"), Vec::<String>::new());
-    assert_eq!(parse_path(":/foo::/bar:"), vec!["/foo", "/bar"]);
-    assert_eq!(parse_path("/foo:/bar:/baz"), vec!["/foo", "/bar", "/baz"]);
+    assert_eq!(parse_path(OsStr::new("")), Vec::<OsString>::new());
+    assert_eq!(parse_path(OsStr::new("::")), Vec::<OsString>::new());
+    assert_eq!(parse_path(OsStr::new(":/foo::/bar:")), vec!["/foo", "/bar"]);
+    assert_eq!(
+        parse_path(OsStr::new("/foo:/bar:/baz")),
+        vec!["/foo", "/bar", "/baz"]
+    );
     assert_eq!(
-        parse_path("/foo:/bar:/foo:/baz:/bar"),
+        parse_path(OsStr::new("/foo:/bar:/foo:/baz:/bar")),
         vec!["/foo", "/bar", "/baz"]
     );
     assert_eq!(
-        parse_raw_path("/foo:/bar:/foo:/baz:/bar"),
+        parse_raw_path(OsStr::new("/foo:/bar:/foo:/baz:/bar")),
         vec!["/foo", "/bar", "/foo", "/baz", "/bar"]
     );
 }
 
+#[test]
+fn test_non_utf8_path() {
+    let latin1 = OsStr::from_bytes(b"/opt/caf\xe9/bin");
+    let path = [latin1, OsStr::new("/bin")].join(OsStr::new(":"));
+    assert_eq!(parse_path(&path), vec![latin1, OsStr::new("/bin")]);
+    assert_eq!(to_os_string(&parse_path(&path)), path);
+    assert_eq!(
+        clean_path(OsStr::from_bytes(b"/opt//caf\xe9/./bin/")),
+        latin1
+    );
+
+    let root = temp_dir("non-utf8");
+    let name = OsStr::from_bytes(b"caf\xe9");
+    fs::write(Path::new(&root).join(name), "").unwrap();
+    assert_eq!(
+        files_in_dir(&root).unwrap(),
+        BTreeSet::from([name.to_os_string()])
+    );
+
+    let env_var = "TEST_PATH_NON_UTF8".to_string();
+    unsafe {
+        set_var(&env_var, &path);
+    }
+    let cli = Cli {
+        env: env_var,
+        command: Commands::Add {
+            directories: vec![dir("a")],
+        },
+        ..Cli::default()
+    };
+    let mut buf = Vec::new();
+    main_logic(cli, &mut buf).unwrap();
+    let mut expected = [dir("a"), path].join(OsStr::new(":")).into_vec();
+    expected.push(b'\n');
+    assert_eq!(buf, expected);
+}
+
+#[test]
+fn test_read_path() {
+    let env_var = "TEST_PATH_READ".to_string();
+    let cli = Cli {
+        env: env_var.clone(),
+        ..Cli::default()
+    };
+    let strict = Cli {
+        strict: true,
+        ..cli.clone()
+    };
+    let defaulted = Cli {
+        default: Some(dir("a")),
+        ..strict.clone()
+    };
+    unsafe {
+        env::remove_var(&env_var);
+    }
+    assert_eq!(
+        cli.read_path().map_err(|e| e.to_string()),
+        Ok((OsString::new(), PathSource::Unset))
+    );
+    assert_eq!(
+        strict.read_path().map_err(|e| e.to_string()),
+        Err(format!("{env_var} is not set"))
+    );
+    assert_eq!(
+        defaulted.read_path().map_err(|e| e.to_string()),
+        Ok((dir("a"), PathSource::Default))
+    );
+
+    unsafe {
+        set_var(&env_var, "");
+    }
+    assert_eq!(
+        strict.read_path().map_err(|e| e.to_string()),
+        Ok((OsString::new(), PathSource::Empty))
+    );
+
+    unsafe {
+        set_var(&env_var, OsStr::from_bytes(b"/caf\xe9"));
+    }
+    assert_eq!(
+        cli.read_path().map_err(|e| e.to_string()),
+        Ok((OsStr::from_bytes(b"/caf\xe9").into(), PathSource::Set))
+    );
+    assert_eq!(
+        defaulted.read_path().map_err(|e| e.to_string()),
+        Err(format!("{env_var} is not valid Unicode"))
+    );
+
+    unsafe {
+        env::remove_var(&env_var);
+    }
+    let cli = Cli {
+        command: Commands::Analyze(AnalyzeArgs {
+            only: vec![Section::Invalid],
+            ..AnalyzeArgs::default()
+        }),
+        ..cli
+    };
+    let mut buf = Vec::new();
+    main_logic(cli.clone(), &mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        format!("Note: {env_var} is not set\n\nInvalid Directories:\n    None\n")
+    );
+
+    let mut analysis = Analysis::new(OsStr::new(""), &[Section::Invalid], ALL_FILES).unwrap();
+    analysis.set_source(&env_var, PathSource::Empty);
+    assert_eq!(
+        report::findings(&analysis),
+        vec![
+            Finding::new(
+                Rule::EmptyPath,
+                String::new(),
+                format!("{env_var} is set but empty")
+            )
+            .with_variable(Some(env_var.clone()))
+        ]
+    );
+    analysis.set_source(&env_var, PathSource::Default);
+    assert_eq!(report::findings(&analysis), vec![]);
+}
+
 #[test]
 fn test_print() {
     let env_var = "TEST_PATH_PRINT".to_string();
@@ -246,7 +503,7 @@ fn test_print() {
         command: Commands::Print,
         ..Cli::default()
     };
-    let path = vec![dir("b"), dir("c"), dir("z")].join(":");
+    let path = vec![dir("b"), dir("c"), dir("z")].join(OsStr::new(":"));
     let cli = base_cli.clone();
     unsafe {
         set_var(env_var.to_owned(), path);
@@ -255,7 +512,12 @@ fn test_print() {
     main_logic(cli, &mut buf).unwrap();
     assert_eq!(
         String::from_utf8(buf).unwrap(),
-        format!("{}\n{}\n{}\n", dir("b"), dir("c"), dir("z"))
+        format!(
+            "{}\n{}\n{}\n",
+            dir("b").display(),
+            dir("c").display(),
+            dir("z").display()
+        )
     );
 
     let cli = Cli {
@@ -266,7 +528,7 @@ fn test_print() {
     main_logic(cli, &mut buf).unwrap();
     assert_eq!(
         String::from_utf8(buf).unwrap(),
-        format!("{}\n{}\n", dir("b"), dir("c"))
+        format!("{}\n{}\n", dir("b").display(), dir("c").display())
     );
 
     let cli = Cli {
@@ -277,7 +539,33 @@ fn test_print() {
     main_logic(cli, &mut buf).unwrap();
     assert_eq!(
         String::from_utf8(buf).unwrap(),
-        format!("{}\n{}\n", normal_dir("b"), normal_dir("c"))
+        format!(
+            "{}\n{}\n",
+            normal_dir("b").display(),
+            normal_dir("c").display()
+        )
+    );
+
+    unsafe {
+        set_var(
+            &env_var,
+            [dir("b/"), dir("la"), dir("z//"), dir("b")].join(OsStr::new(":")),
+        );
+    }
+    let cli = Cli {
+        clean: true,
+        ..base_cli.clone()
+    };
+    let mut buf = Vec::new();
+    main_logic(cli, &mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        format!(
+            "{}\n{}\n{}\n",
+            dir("b").display(),
+            dir("la").display(),
+            dir("z").display()
+        )
     );
 }
 
@@ -313,7 +601,9 @@ fn test_new() {
             dir("x"),
             dir("b/bb")
         ]
-        .join(":")
+        .join(OsStr::new(":"))
+        .display()
+        .to_string()
             + "\n"
     );
 
@@ -325,7 +615,11 @@ fn test_new() {
     main_logic(cli, &mut buf).unwrap();
     assert_eq!(
         String::from_utf8(buf).unwrap(),
-        vec![dir("la"), dir("b"), dir("a"), dir("c"), dir("b/bb")].join(":") + "\n"
+        vec![dir("la"), dir("b"), dir("a"), dir("c"), dir("b/bb")]
+            .join(OsStr::new(":"))
+            .display()
+            .to_string()
+            + "\n"
     );
 
     let cli = Cli {
@@ -342,7 +636,9 @@ fn test_new() {
             normal_dir("c"),
             normal_dir("b/bb")
         ]
-        .join(":")
+        .join(OsStr::new(":"))
+        .display()
+        .to_string()
             + "\n"
     );
 }
@@ -357,7 +653,7 @@ fn test_add() {
         },
         ..Cli::default()
     };
-    let path = vec![dir("b"), dir("a"), dir("c"), dir("z")].join(":");
+    let path = vec![dir("b"), dir("a"), dir("c"), dir("z")].join(OsStr::new(":"));
     let cli = base_cli.clone();
     unsafe {
         set_var(env_var.to_owned(), path);
@@ -366,7 +662,11 @@ fn test_add() {
     main_logic(cli, &mut buf).unwrap();
     assert_eq!(
         String::from_utf8(buf).unwrap(),
-        vec![dir("la"), dir("x"), dir("b"), dir("a"), dir("c"), dir("z")].join(":") + "\n"
+        vec![dir("la"), dir("x"), dir("b"), dir("a"), dir("c"), dir("z")]
+            .join(OsStr::new(":"))
+            .display()
+            .to_string()
+            + "\n"
     );
 
     let cli = Cli {
@@ -377,7 +677,11 @@ fn test_add() {
     main_logic(cli, &mut buf).unwrap();
     assert_eq!(
         String::from_utf8(buf).unwrap(),
-        vec![dir("la"), dir("b"), dir("a"), dir("c")].join(":") + "\n"
+        vec![dir("la"), dir("b"), dir("a"), dir("c")]
+            .join(OsStr::new(":"))
+            .display()
+            .to_string()
+            + "\n"
     );
 
     let cli = Cli {
@@ -388,7 +692,11 @@ fn test_add() {
     main_logic(cli, &mut buf).unwrap();
     assert_eq!(
         String::from_utf8(buf).unwrap(),
-        vec![normal_dir("a"), normal_dir("b"), normal_dir("c")].join(":") + "\n"
+        vec![normal_dir("a"), normal_dir("b"), normal_dir("c")]
+            .join(OsStr::new(":"))
+            .display()
+            .to_string()
+            + "\n"
     );
 }
 
@@ -402,7 +710,7 @@ fn test_append() {
         },
         ..Cli::default()
     };
-    let path = vec![dir("b"), dir("a"), dir("c"), dir("z")].join(":");
+    let path = vec![dir("b"), dir("a"), dir("c"), dir("z")].join(OsStr::new(":"));
     let cli = base_cli.clone();
     unsafe {
         set_var(env_var.to_owned(), path);
@@ -411,7 +719,11 @@ fn test_append() {
     main_logic(cli, &mut buf).unwrap();
     assert_eq!(
         String::from_utf8(buf).unwrap(),
-        vec![dir("b"), dir("a"), dir("c"), dir("z"), dir("la"), dir("x")].join(":") + "\n"
+        vec![dir("b"), dir("a"), dir("c"), dir("z"), dir("la"), dir("x")]
+            .join(OsStr::new(":"))
+            .display()
+            .to_string()
+            + "\n"
     );
 
     let cli = Cli {
@@ -422,7 +734,11 @@ fn test_append() {
     main_logic(cli, &mut buf).unwrap();
     assert_eq!(
         String::from_utf8(buf).unwrap(),
-        vec![dir("b"), dir("a"), dir("c"), dir("la")].join(":") + "\n"
+        vec![dir("b"), dir("a"), dir("c"), dir("la")]
+            .join(OsStr::new(":"))
+            .display()
+            .to_string()
+            + "\n"
     );
 
     let cli = Cli {
@@ -433,17 +749,105 @@ fn test_append() {
     main_logic(cli, &mut buf).unwrap();
     assert_eq!(
         String::from_utf8(buf).unwrap(),
-        vec![normal_dir("b"), normal_dir("a"), normal_dir("c")].join(":") + "\n"
+        vec![normal_dir("b"), normal_dir("a"), normal_dir("c")]
+            .join(OsStr::new(":"))
+            .display()
+            .to_string()
+            + "\n"
     );
 }
 
 #[test]
 fn test_get_invalid_dirs() {
-    let path = vec![dir("laa"), dir("broken"), dir("a"), dir("c"), dir("z")].join(":");
+    let path = vec![
+        dir("laa"),
+        dir("broken"),
+        dir("a"),
+        dir("c"),
+        dir("z"),
+        dir("a/keepme.txt"),
+        dir("d/loop"),
+        dir("broken2"),
+    ]
+    .join(OsStr::new(":"));
+    assert_eq!(
+        get_invalid_dirs(&path),
+        vec![
+            InvalidDir::new(dir("broken"), InvalidReason::BrokenSymlink, None),
+            InvalidDir::new(dir("z"), InvalidReason::Missing, None),
+            InvalidDir::new(dir("a/keepme.txt"), InvalidReason::RegularFile, None),
+            InvalidDir::new(dir("d/loop"), InvalidReason::SymlinkLoop, None),
+            InvalidDir::new(dir("broken2"), InvalidReason::BrokenSymlink, None),
+        ]
+    );
+}
+
+#[test]
+fn test_get_invalid_reason_permission() {
+    let root = temp_dir("invalid-permission");
+    let locked = Path::new(&root).join("locked");
+    fs::create_dir_all(locked.join("bin")).unwrap();
+    let link = Path::new(&root).join("link");
+    std::os::unix::fs::symlink(locked.join("bin"), &link).unwrap();
+    chmod(&locked, 0o000);
+    // Root can search any directory so both entries are valid then.
+    let expected = if current_uid() == 0 {
+        None
+    } else {
+        Some(InvalidReason::PermissionDenied)
+    };
+    assert_eq!(get_invalid_reason(locked.join("bin").as_os_str()), expected);
+    assert_eq!(get_invalid_reason(link.as_os_str()), expected);
+    chmod(&locked, 0o755);
+}
+
+#[test]
+fn test_suggest_dir() {
+    let root = temp_dir("suggest");
+    for d in [
+        "go1.9/bin",
+        "go1.22/bin",
+        "go1.21/bin",
+        "python3/bin",
+        "tools",
+    ] {
+        fs::create_dir_all(Path::new(&root).join(d)).unwrap();
+    }
+    let path = |s: &str| OsString::from(format!("{}/{}", root.display(), s));
+    assert_eq!(suggest_dir(&path("go1.20/bin")), Some(path("go1.22/bin")));
+    assert_eq!(suggest_dir(&path("go1.20")), Some(path("go1.22")));
+    assert_eq!(suggest_dir(&path("pyhton3/bin")), Some(path("python3/bin")));
+    assert_eq!(suggest_dir(&path("tols")), Some(path("tools")));
+    assert_eq!(suggest_dir(&path("tools/bin")), None);
+    assert_eq!(suggest_dir(&path("xyz/bin")), None);
+    assert_eq!(suggest_dir(&dir("z")), None);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("same", "same"), 0);
+
+    let invalid = get_invalid_dirs(&path("go1.20/bin"));
+    assert_eq!(
+        invalid,
+        vec![InvalidDir::new(
+            path("go1.20/bin"),
+            InvalidReason::Missing,
+            Some(path("go1.22/bin"))
+        )]
+    );
     assert_eq!(
-        get_invalid_dirs(path.as_str()),
-        vec!(dir("broken"), dir("z"))
+        invalid[0].describe(),
+        format!(
+            "does not exist, did you mean {}?",
+            path("go1.22/bin").display()
+        )
     );
+    let analysis = Analysis::new(&path("go1.20/bin"), &[Section::Invalid], ALL_FILES).unwrap();
+    let mut buf = Vec::new();
+    report::write_json_lines(&mut buf, &report::findings(&analysis)).unwrap();
+    assert!(String::from_utf8(buf).unwrap().contains(&format!(
+        "\"suggestion\":\"{}\"",
+        path("go1.22/bin").display()
+    )));
 }
 
 #[test]
@@ -456,30 +860,1093 @@ fn test_get_duplicate_dirs() {
         dir("laa"),
         dir("a"),
     ]
-    .join(":");
+    .join(OsStr::new(":"));
+    assert_eq!(get_duplicate_dirs(&path), vec!(dir("laa"), dir("a")));
+}
+
+#[test]
+fn test_get_unreadable_dirs() {
+    let locked = temp_dir("unreadable");
+    chmod(&locked, 0o000);
+    let path = [locked.clone(), dir("a/keepme.txt"), dir("b"), dir("c")].join(OsStr::new(":"));
+    // Root can list any directory so only the remaining entries are checked then.
+    let expected = if current_uid() == 0 {
+        vec![]
+    } else {
+        vec![locked.clone()]
+    };
+    let (shadows, unreadable) = get_shadowed(&path, ALL_FILES);
     assert_eq!(
-        get_duplicate_dirs(path.as_str()),
-        vec!(dir("laa"), dir("a"))
+        unreadable.iter().map(|u| u.dir.clone()).collect::<Vec<_>>(),
+        expected
     );
+    assert_eq!(
+        shadows.iter().map(|(d, _)| d.clone()).collect::<Vec<_>>(),
+        vec![dir("c")]
+    );
+    assert_eq!(get_unreadable_dirs(&path), unreadable);
+    assert_eq!(get_commands(&parse_path(&path), ALL_FILES).1, unreadable);
+    let analysis = Analysis::new(&path, &[Section::Unreadable], ALL_FILES).unwrap();
+    assert_eq!(analysis.unreadable, unreadable);
+    assert_eq!(analysis.count(Section::Unreadable), expected.len());
+    let analysis = Analysis::new(&path, &[Section::Shadows], ALL_FILES).unwrap();
+    assert!(analysis.unreadable.is_empty());
+    chmod(&locked, 0o755);
 }
 
 #[test]
 fn test_get_shadowed() {
-    let path = vec![dir("a"), dir("b"), dir("c")].join(":");
+    let path = vec![dir("a"), dir("b"), dir("c")].join(OsStr::new(":"));
     assert_eq!(
-        get_shadowed(path.as_str()).unwrap(),
+        get_shadowed(&path, ALL_FILES).0,
         vec![
             (
                 dir("b"),
-                vec![Shadow::new(dir("a"), "keepme.txt".to_string()),]
+                vec![Shadow::new(
+                    dir("a"),
+                    "keepme.txt".into(),
+                    ShadowKind::IdenticalContent
+                ),]
             ),
             (
                 dir("c"),
                 vec![
-                    Shadow::new(dir("a"), "keepme.txt".to_string()),
-                    Shadow::new(dir("b"), "x".to_string()),
+                    Shadow::new(dir("a"), "keepme.txt".into(), ShadowKind::IdenticalContent),
+                    Shadow::new(dir("b"), "x".into(), ShadowKind::IdenticalContent),
                 ]
             ),
         ]
     );
 }
+
+#[test]
+fn test_get_dir_stats() {
+    let stats = |dir: OsString, commands, reachable, shadows, shadowed| DirStats {
+        dir,
+        commands,
+        reachable,
+        shadows,
+        shadowed,
+    };
+    let path = [dir("a"), dir("z"), dir("b"), dir("c")].join(OsStr::new(":"));
+    assert_eq!(
+        get_dir_stats(&path, ALL_FILES),
+        vec![
+            stats(dir("a"), 1, 1, 2, 0),
+            stats(dir("z"), 0, 0, 0, 0),
+            stats(dir("b"), 2, 1, 1, 1),
+            stats(dir("c"), 2, 0, 0, 2),
+        ]
+    );
+
+    let env_var = "TEST_PATH_ANALYZE_STATS".to_string();
+    unsafe {
+        set_var(&env_var, [dir("b"), dir("c")].join(OsStr::new(":")));
+    }
+    let cli = Cli {
+        env: env_var,
+        command: Commands::Analyze(AnalyzeArgs {
+            only: vec![Section::Duplicates],
+            stats: true,
+            ..AnalyzeArgs::default()
+        }),
+        ..Cli::default()
+    };
+    let mut buf = Vec::new();
+    main_logic(cli.clone(), &mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        format!(
+            "Duplicate Directories:\n    None\n\n\
+             Directory Statistics:\n\
+             \x20   commands  reachable  shadows  shadowed  directory\n\
+             \x20          1          1        1         0  {}\n\
+             \x20          1          0        0         1  {}\n",
+            dir("b").display(),
+            dir("c").display()
+        )
+    );
+
+    let cli = Cli {
+        command: Commands::Analyze(AnalyzeArgs {
+            stats: true,
+            format: ReportFormat::Sarif,
+            ..AnalyzeArgs::default()
+        }),
+        ..cli
+    };
+    assert_eq!(
+        main_logic(cli, &mut Vec::new()).unwrap_err().to_string(),
+        "--stats cannot be used with --format sarif"
+    );
+}
+
+#[test]
+fn test_get_commands() {
+    let path = vec![dir("a"), dir("b"), dir("z")];
+    assert_eq!(
+        get_commands(&path, ALL_FILES),
+        (
+            BTreeMap::from([("keepme.txt".into(), dir("a")), ("x".into(), dir("b")),]),
+            vec![]
+        )
+    );
+}
+
+#[test]
+fn test_get_impact() {
+    assert_eq!(
+        get_impact(&[dir("a"), dir("b")], &[dir("a"), dir("b")], ALL_FILES),
+        vec![]
+    );
+    assert_eq!(
+        get_impact(&[dir("a")], &[dir("c"), dir("b")], ALL_FILES),
+        vec![
+            CommandChange::new("keepme.txt".into(), Some(dir("a")), Some(dir("c"))),
+            CommandChange::new("x".into(), None, Some(dir("c"))),
+        ]
+    );
+    assert_eq!(
+        get_impact(&[dir("b")], &[dir("a")], ALL_FILES),
+        vec![
+            CommandChange::new("keepme.txt".into(), Some(dir("b")), Some(dir("a"))),
+            CommandChange::new("x".into(), Some(dir("b")), None),
+        ]
+    );
+}
+
+#[test]
+fn test_impact() {
+    let env_var = "TEST_PATH_IMPACT".to_string();
+    let cli = Cli {
+        env: env_var.clone(),
+        command: Commands::Impact {
+            new_path: [dir("c"), dir("a")].join(OsStr::new(":")),
+            filter: ALL_FILES,
+        },
+        ..Cli::default()
+    };
+    unsafe {
+        set_var(env_var, [dir("a"), dir("b")].join(OsStr::new(":")));
+    }
+    let mut buf = Vec::new();
+    main_logic(cli, &mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        format!(
+            "Changed Commands:\n    keepme.txt  {}  =>  {}\n    x  {}  =>  {}\n\n\
+             Added Commands:\n    None\n\n\
+             Removed Commands:\n    None\n",
+            dir("a").display(),
+            dir("c").display(),
+            dir("b").display(),
+            dir("c").display()
+        )
+    );
+}
+
+#[test]
+fn test_find_command() {
+    let path = [dir("a"), dir("b"), dir("c"), dir("d")].join(OsStr::new(":"));
+    let filter = CommandFilter::default();
+    assert_eq!(
+        find_command(&path, OsStr::new("x"), filter),
+        vec![
+            Candidate::new(dir("b"), None),
+            Candidate::new(dir("c"), Some(ShadowKind::IdenticalContent)),
+            Candidate::new(dir("d"), Some(ShadowKind::Symlink)),
+        ]
+    );
+    assert_eq!(
+        find_command(&path, OsStr::new("keepme.txt"), filter),
+        vec![]
+    );
+    assert_eq!(
+        find_command(&path, OsStr::new("keepme.txt"), ALL_FILES),
+        vec![
+            Candidate::new(dir("a"), None),
+            Candidate::new(dir("b"), Some(ShadowKind::IdenticalContent)),
+            Candidate::new(dir("c"), Some(ShadowKind::IdenticalContent)),
+        ]
+    );
+    assert_eq!(find_command(&path, &dir("b/x"), filter), vec![]);
+}
+
+#[test]
+fn test_which() {
+    let env_var = "TEST_PATH_WHICH".to_string();
+    unsafe {
+        set_var(
+            &env_var,
+            [dir("a"), dir("b"), dir("c")].join(OsStr::new(":")),
+        );
+    }
+    let which = |all: bool, names: &[&str]| {
+        let cli = Cli {
+            env: env_var.clone(),
+            command: Commands::Which {
+                all,
+                names: strings(names),
+                filter: CommandFilter::default(),
+            },
+            ..Cli::default()
+        };
+        let mut buf = Vec::new();
+        let status = main_logic(cli, &mut buf).unwrap();
+        (status, String::from_utf8(buf).unwrap())
+    };
+    assert_eq!(
+        which(false, &["x"]),
+        (ExitCode::SUCCESS, format!("{}/x\n", dir("b").display()))
+    );
+    assert_eq!(
+        which(true, &["x", "missing"]),
+        (
+            ExitCode::FAILURE,
+            format!(
+                "{}/x\n{}/x  (shadowed, identical content)\n",
+                dir("b").display(),
+                dir("c").display()
+            )
+        )
+    );
+}
+
+#[test]
+fn test_explain_command() {
+    let path = [dir("a"), dir("z"), dir("b"), dir("c"), dir("d")].join(OsStr::new(":"));
+    assert_eq!(
+        explain_command(&path, OsStr::new("x"), CommandFilter::default()),
+        vec![
+            (dir("a"), Lookup::NoFile),
+            (dir("z"), Lookup::Invalid(InvalidReason::Missing)),
+            (dir("b"), Lookup::Runs(vec![])),
+            (dir("c"), Lookup::Shadowed(ShadowKind::IdenticalContent)),
+            (dir("d"), Lookup::Shadowed(ShadowKind::Symlink)),
+        ]
+    );
+    let no_symlinks = CommandFilter {
+        no_symlinks: true,
+        ..CommandFilter::default()
+    };
+    assert_eq!(
+        explain_command(&path, OsStr::new("x"), no_symlinks)[4],
+        (dir("d"), Lookup::SymlinkIgnored)
+    );
+    assert_eq!(
+        explain_command(&path, OsStr::new("keepme.txt"), CommandFilter::default())[0],
+        (dir("a"), Lookup::NotExecutable)
+    );
+    assert_eq!(
+        explain_command(&path, OsStr::new("bb"), ALL_FILES)[2],
+        (dir("b"), Lookup::NotFile)
+    );
+    assert_eq!(
+        explain_command(&path, OsStr::new("gone"), ALL_FILES)[4],
+        (dir("d"), Lookup::BrokenSymlink)
+    );
+    assert_eq!(explain_command(&path, &dir("b/x"), ALL_FILES), vec![]);
+
+    let locked = temp_dir("unsearchable");
+    chmod(&locked, 0o000);
+    // Root can search any directory so the name is simply not found then.
+    let expected = if current_uid() == 0 {
+        Lookup::NoFile
+    } else {
+        Lookup::Unsearchable
+    };
+    assert_eq!(
+        explain_command(&locked, OsStr::new("x"), ALL_FILES),
+        vec![(locked.clone(), expected)]
+    );
+    chmod(&locked, 0o755);
+}
+
+#[test]
+fn test_why() {
+    let env_var = "TEST_PATH_WHY".to_string();
+    unsafe {
+        set_var(
+            &env_var,
+            [dir("z"), dir("d"), dir("b")].join(OsStr::new(":")),
+        );
+    }
+    let why = |name: &str| {
+        let cli = Cli {
+            env: env_var.clone(),
+            command: Commands::Why {
+                name: name.into(),
+                filter: CommandFilter::default(),
+            },
+            ..Cli::default()
+        };
+        let mut buf = Vec::new();
+        let status = main_logic(cli, &mut buf).unwrap();
+        (status, String::from_utf8(buf).unwrap())
+    };
+    assert_eq!(
+        why("x"),
+        (
+            ExitCode::SUCCESS,
+            format!(
+                "Searching {env_var} for x:\n\
+                 \x20   {}  does not exist\n\
+                 \x20   {}  x runs\n\
+                 \x20       ->  {}\n\
+                 \x20   {}  x is shadowed (symlink to same file)\n\n\
+                 x runs {}/x\n",
+                dir("z").display(),
+                dir("d").display(),
+                dir("d/../b/x").display(),
+                dir("b").display(),
+                dir("d").display()
+            )
+        )
+    );
+    assert_eq!(why("missing").0, ExitCode::FAILURE);
+}
+
+#[test]
+fn test_commands() {
+    let env_var = "TEST_PATH_COMMANDS".to_string();
+    unsafe {
+        set_var(
+            &env_var,
+            [dir("a"), dir("b"), dir("c")].join(OsStr::new(":")),
+        );
+    }
+    let commands = |regex: Option<&str>| {
+        let cli = Cli {
+            env: env_var.clone(),
+            command: Commands::CommandList {
+                regex: regex.map(str::to_string),
+                filter: ALL_FILES,
+            },
+            ..Cli::default()
+        };
+        let mut buf = Vec::new();
+        main_logic(cli, &mut buf).map_err(|e| e.to_string())?;
+        Ok(String::from_utf8(buf).unwrap())
+    };
+    assert_eq!(
+        commands(None),
+        Ok(format!(
+            "keepme.txt\t{}\nx\t{}\n",
+            dir("a").display(),
+            dir("b").display()
+        ))
+    );
+    assert_eq!(
+        commands(Some("^x$")),
+        Ok(format!("x\t{}\n", dir("b").display()))
+    );
+    assert_eq!(
+        commands(Some("(")),
+        Err("Invalid --regex pattern".to_string())
+    );
+}
+
+#[test]
+fn test_analysis_ignore() {
+    let path = [dir("a"), dir("z"), dir("b"), dir("c"), dir("a")].join(OsStr::new(":"));
+    let sections = &[Section::Invalid, Section::Duplicates, Section::Shadows];
+    let mut analysis = Analysis::new(&path, sections, ALL_FILES).unwrap();
+    analysis.ignore(&[
+        dir("z"),
+        "keepme.txt".into(),
+        format!("{}/x", dir("c").display()).into(),
+    ]);
+    assert_eq!(
+        analysis,
+        Analysis {
+            sections: sections.to_vec(),
+            invalid: vec![],
+            duplicates: vec![dir("a")],
+            shadows: vec![],
+            ..Analysis::default()
+        }
+    );
+
+    let path = [dir("a"), dir("b"), dir("c")].join(OsStr::new(":"));
+    let mut analysis = Analysis::new(&path, sections, ALL_FILES).unwrap();
+    analysis.ignore(&[dir("b")]);
+    assert_eq!(
+        analysis.shadows,
+        vec![(
+            dir("c"),
+            vec![
+                Shadow::new(dir("a"), "keepme.txt".into(), ShadowKind::IdenticalContent),
+                Shadow::new(dir("b"), "x".into(), ShadowKind::IdenticalContent),
+            ]
+        )]
+    );
+}
+
+#[test]
+fn test_analyze_check() {
+    let env_var = "TEST_PATH_ANALYZE_CHECK".to_string();
+    let path = [dir("a"), dir("z"), dir("b"), dir("a")].join(OsStr::new(":"));
+    unsafe {
+        set_var(&env_var, path);
+    }
+    let analyze = |args: AnalyzeArgs| {
+        let cli = Cli {
+            env: env_var.to_owned(),
+            command: Commands::Analyze(args),
+            ..Cli::default()
+        };
+        main_logic(cli, &mut Vec::new()).unwrap()
+    };
+    let check = AnalyzeArgs {
+        check: true,
+        fail_on: Some(vec![Section::Invalid, Section::Duplicates]),
+        filter: ALL_FILES,
+        ..AnalyzeArgs::default()
+    };
+
+    assert_eq!(
+        analyze(AnalyzeArgs {
+            check: false,
+            ..check.clone()
+        }),
+        ExitCode::SUCCESS
+    );
+    assert_eq!(analyze(check.clone()), ExitCode::from(10));
+    assert_eq!(
+        analyze(AnalyzeArgs {
+            ignore: vec![dir("z")],
+            ..check.clone()
+        }),
+        ExitCode::from(11)
+    );
+    assert_eq!(
+        analyze(AnalyzeArgs {
+            fail_on: Some(vec![Section::Shadows]),
+            ..check.clone()
+        }),
+        ExitCode::from(12)
+    );
+    assert_eq!(
+        analyze(AnalyzeArgs {
+            ignore: vec![dir("z"), dir("a")],
+            ..check.clone()
+        }),
+        ExitCode::SUCCESS
+    );
+    assert_eq!(
+        analyze(AnalyzeArgs {
+            fail_on: None,
+            only: vec![Section::Duplicates],
+            ..check.clone()
+        }),
+        ExitCode::from(11)
+    );
+    assert_eq!(
+        analyze(AnalyzeArgs {
+            fail_on: None,
+            skip: vec![Section::Invalid, Section::Duplicates],
+            ..check.clone()
+        }),
+        ExitCode::SUCCESS
+    );
+
+    let cli = Cli {
+        env: env_var.to_owned(),
+        command: Commands::Analyze(AnalyzeArgs {
+            skip: vec![Section::Duplicates],
+            ..check.clone()
+        }),
+        ..Cli::default()
+    };
+    assert_eq!(
+        main_logic(cli, &mut Vec::new()).unwrap_err().to_string(),
+        "--fail-on duplicates names a section that is not run"
+    );
+}
+
+#[test]
+fn test_analyze_sections() {
+    let env_var = "TEST_PATH_ANALYZE_SECTIONS".to_string();
+    let path = [dir("a"), dir("z"), dir("b"), dir("a")].join(OsStr::new(":"));
+    unsafe {
+        set_var(&env_var, path);
+    }
+    let analyze = |args: AnalyzeArgs| {
+        let cli = Cli {
+            env: env_var.to_owned(),
+            command: Commands::Analyze(args),
+            ..Cli::default()
+        };
+        let mut buf = Vec::new();
+        main_logic(cli, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    };
+
+    assert_eq!(
+        AnalyzeArgs::default().sections(),
+        Section::value_variants().to_vec()
+    );
+    let skipped = AnalyzeArgs {
+        skip: vec![Section::Shadows],
+        ..AnalyzeArgs::default()
+    }
+    .sections();
+    assert!(skipped.contains(&Section::Invalid));
+    assert!(!skipped.contains(&Section::Shadows));
+    assert_eq!(
+        analyze(AnalyzeArgs {
+            only: vec![Section::Duplicates, Section::Invalid],
+            ..AnalyzeArgs::default()
+        }),
+        format!(
+            "Invalid Directories:\n    {}  (does not exist)\n\nDuplicate Directories:\n    {}\n",
+            dir("z").display(),
+            dir("a").display()
+        )
+    );
+    assert_eq!(
+        analyze(AnalyzeArgs {
+            only: vec![Section::Shadows],
+            filter: ALL_FILES,
+            ..AnalyzeArgs::default()
+        }),
+        format!(
+            "Shadowed Files:\n    {}\n        keepme.txt  =>  {}  (identical content)\n\n    {}\n        keepme.txt  =>  {}  (same file)\n",
+            dir("b").display(),
+            dir("a").display(),
+            dir("a").display(),
+            dir("a").display()
+        )
+    );
+}
+
+#[test]
+fn test_findings() {
+    let path = [dir("a"), dir("z"), dir("b"), dir("a")].join(OsStr::new(":"));
+    let sections = &[Section::Invalid, Section::Duplicates, Section::Shadows];
+    let analysis = Analysis::new(&path, sections, ALL_FILES).unwrap();
+    assert_eq!(
+        report::findings(&analysis),
+        vec![
+            Finding::new(
+                Rule::InvalidDir,
+                dir("z").display().to_string(),
+                "does not exist".into()
+            ),
+            Finding::new(
+                Rule::DuplicateDir,
+                dir("a").display().to_string(),
+                "appears earlier in the path".into()
+            ),
+            Finding::new(
+                Rule::BenignShadow,
+                dir("b").display().to_string(),
+                format!(
+                    "keepme.txt is shadowed by {} (identical content)",
+                    dir("a").display()
+                )
+            ),
+            Finding::new(
+                Rule::BenignShadow,
+                dir("a").display().to_string(),
+                format!(
+                    "keepme.txt is shadowed by {} (same file)",
+                    dir("a").display()
+                )
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_analyze_json_lines() {
+    let env_var = "TEST_PATH_ANALYZE_JSONL".to_string();
+    unsafe {
+        set_var(&env_var, [dir("a"), dir("z")].join(OsStr::new(":")));
+    }
+    let cli = Cli {
+        env: env_var,
+        command: Commands::Analyze(AnalyzeArgs {
+            only: vec![Section::Invalid, Section::Duplicates],
+            format: ReportFormat::Jsonl,
+            ..AnalyzeArgs::default()
+        }),
+        ..Cli::default()
+    };
+    let mut buf = Vec::new();
+    main_logic(cli, &mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        format!(
+            "{{\"details\":\"does not exist\",\"directory\":\"{}\",\
+             \"name\":\"invalid-dir\",\"rule\":\"PT001\",\"severity\":\"warning\"}}\n",
+            dir("z").display()
+        )
+    );
+}
+
+#[test]
+fn test_analyze_sarif() {
+    let mut buf = Vec::new();
+    report::write_sarif(
+        &mut buf,
+        &[
+            Finding::new(
+                Rule::DuplicateDir,
+                normal_dir("a").display().to_string(),
+                "appears earlier in the path".into(),
+            ),
+            Finding::new(
+                Rule::InvalidDir,
+                "/opt/my tools/100%".into(),
+                "does not exist".into(),
+            ),
+            Finding::new(Rule::RelativeDir, "bin".into(), "is relative".into()),
+            Finding::new(Rule::RelativeDir, "".into(), "is empty".into()),
+        ],
+    )
+    .unwrap();
+    let log: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    assert_eq!(log["version"], "2.1.0");
+    let run = &log["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "path-tool");
+    assert_eq!(run["tool"]["driver"]["rules"][1]["id"], "PT002");
+    assert_eq!(run["results"][0]["ruleId"], "PT002");
+    assert_eq!(run["results"][0]["level"], "warning");
+    assert_eq!(
+        run["results"][0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+        format!("file://{}", normal_dir("a").display())
+    );
+    assert_eq!(
+        run["results"][1]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+        "file:///opt/my%20tools/100%25"
+    );
+    assert_eq!(
+        run["results"][2]["locations"][0]["logicalLocations"][0]["name"],
+        "bin"
+    );
+    assert!(run["results"][3].get("locations").is_none());
+}
+
+#[test]
+fn test_analyze_markdown() {
+    let path = [dir("a"), dir("z"), dir("b")].join(OsStr::new(":"));
+    let sections = &[Section::Invalid, Section::Duplicates, Section::Shadows];
+    let analysis = Analysis::new(&path, sections, ALL_FILES).unwrap();
+    let mut buf = Vec::new();
+    report::write_markdown(&mut buf, &analysis).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        format!(
+            "# Path Analysis
+
+## Summary
+
+| Section | Problems |
+|---------|----------|
+| Invalid Directories | 1 |
+| Duplicate Directories | 0 |
+| Shadowed Files | 1 |
+
+## Invalid Directories
+
+- `{}`: does not exist
+
+## Duplicate Directories
+
+None
+
+## Shadowed Files
+
+<details>
+<summary><code>{}</code> (1 shadowed)</summary>
+
+| File | Shadowed By | Kind |
+|------|-------------|------|
+| `keepme.txt` | `{}` | identical content |
+
+</details>
+",
+            dir("z").display(),
+            dir("b").display(),
+            dir("a").display()
+        )
+    );
+}
+
+#[test]
+fn test_analyze_html() {
+    let path = [dir("a"), dir("b"), "<z>".into()].join(OsStr::new(":"));
+    let analysis = Analysis::new(&path, &[Section::Invalid, Section::Shadows], ALL_FILES).unwrap();
+    let mut buf = Vec::new();
+    report::write_html(&mut buf, &analysis).unwrap();
+    let html = String::from_utf8(buf).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>\n"));
+    assert!(html.ends_with("</html>\n"));
+    assert!(html.contains("<tr><td>Invalid Directories</td><td>1</td></tr>"));
+    assert!(html.contains("<tr><td>Shadowed Files</td><td>1</td></tr>"));
+    assert!(!html.contains("Duplicate Directories"));
+    assert!(html.contains("<li><code>&lt;z&gt;</code>: does not exist</li>"));
+    assert!(html.contains(&format!(
+        "<summary><code>{}</code> (1 shadowed)</summary>",
+        dir("b").display()
+    )));
+    assert!(html.contains(&format!(
+        "<tr><td><code>keepme.txt</code></td><td><code>{}</code></td><td>identical content</td></tr>",
+        dir("a").display()
+    )));
+
+    let analysis =
+        Analysis::new(OsStr::new("/usr/bin::"), &[Section::Relative], ALL_FILES).unwrap();
+    let mut buf = Vec::new();
+    report::write_html(&mut buf, &analysis).unwrap();
+    assert!(String::from_utf8(buf).unwrap().contains(
+        "<li><code>&quot;&quot;</code>: empty entry searches the current directory</li>"
+    ));
+    let mut buf = Vec::new();
+    report::write_markdown(&mut buf, &analysis).unwrap();
+    assert!(
+        String::from_utf8(buf)
+            .unwrap()
+            .contains("- `\"\"`: empty entry searches the current directory\n")
+    );
+}
+
+#[test]
+fn test_get_relative_dirs() {
+    assert_eq!(get_relative_dirs(OsStr::new("")), vec![]);
+    assert_eq!(get_relative_dirs(OsStr::new("/usr/bin:/bin")), vec![]);
+    assert_eq!(
+        get_relative_dirs(OsStr::new(":/usr/bin:.:bin:/usr/../tmp:/usr/./bin:./x:")),
+        vec![
+            RelativeDir::new("".into(), RelativeReason::Empty),
+            RelativeDir::new(".".into(), RelativeReason::Relative),
+            RelativeDir::new("bin".into(), RelativeReason::Relative),
+            RelativeDir::new("/usr/../tmp".into(), RelativeReason::ParentComponent),
+            RelativeDir::new("/usr/./bin".into(), RelativeReason::CurrentComponent),
+            RelativeDir::new("./x".into(), RelativeReason::Relative),
+            RelativeDir::new("".into(), RelativeReason::Empty),
+        ]
+    );
+}
+
+#[test]
+fn test_get_insecure_dirs() {
+    let root = temp_dir("insecure").into_string().unwrap();
+    let safe = format!("{}/safe", root);
+    let group = format!("{}/group", root);
+    let world = format!("{}/world", root);
+    let open = format!("{}/open", root);
+    let sticky = format!("{}/sticky", root);
+    let in_open = format!("{}/bin", open);
+    let in_sticky = format!("{}/bin", sticky);
+    for d in [&safe, &group, &world, &in_open, &in_sticky] {
+        fs::create_dir_all(d).unwrap();
+        chmod(d, 0o755);
+    }
+    chmod(&group, 0o775);
+    chmod(&world, 0o757);
+    chmod(&open, 0o777);
+    chmod(&sticky, 0o1777);
+
+    let path = [&safe, &group, &world, &in_open, &in_sticky]
+        .iter()
+        .join(":");
+    assert_eq!(
+        get_insecure_dirs(OsStr::new(&path)),
+        vec![
+            InsecureDir::new(group.into(), PermissionProblem::GroupWritable),
+            InsecureDir::new(world.into(), PermissionProblem::WorldWritable),
+            InsecureDir::new(
+                in_open.into(),
+                PermissionProblem::WritableParent(fs::canonicalize(&open).unwrap())
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_get_hijack_risks() {
+    let open = temp_dir("hijack");
+    chmod(&open, 0o777);
+
+    // nothing trusted follows the writable directory
+    assert_eq!(
+        get_hijack_risks(
+            &[OsStr::new("/bin"), &open].join(OsStr::new(":")),
+            CommandFilter::default()
+        )
+        .unwrap(),
+        vec![]
+    );
+
+    let risks = get_hijack_risks(
+        &[&open, OsStr::new("/bin")].join(OsStr::new(":")),
+        CommandFilter::default(),
+    )
+    .unwrap();
+    assert_eq!(risks.len(), 1);
+    let risk = &risks[0];
+    assert_eq!(risk.dir, open);
+    assert!(risk.writers.contains(&Writer::Group));
+    assert!(risk.writers.contains(&Writer::Everyone));
+    assert!(risk.commands.contains(&("sh".into(), "/bin".into())));
+}
+
+#[test]
+fn test_get_file_problems() {
+    let safe = temp_dir("file-problems-safe");
+    let open = temp_dir("file-problems-open");
+    let create = |dir: &OsStr, file: &str, mode: u32| {
+        let path = Path::new(dir).join(file);
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        chmod(&path, mode);
+    };
+    create(&safe, "readme", 0o644);
+    create(&safe, "tool", 0o755);
+    create(&safe, "shared", 0o775);
+    create(&safe, "suid", 0o4755);
+    create(&open, "suid", 0o4755);
+    create(&open, "sgid", 0o2755);
+    // Read only so the directory is not writable by the current user either.
+    chmod(&safe, 0o555);
+    chmod(&open, 0o777);
+
+    assert_eq!(
+        get_file_problems(&[safe.clone(), open.clone()].join(OsStr::new(":"))).unwrap(),
+        vec![
+            (
+                safe.clone(),
+                vec![
+                    FileProblem::new("readme".into(), FileProblemKind::NotExecutable),
+                    FileProblem::new("shared".into(), FileProblemKind::WritableByOthers),
+                ]
+            ),
+            (
+                open.clone(),
+                vec![
+                    FileProblem::new("sgid".into(), FileProblemKind::SetIdInWritableDir),
+                    FileProblem::new("suid".into(), FileProblemKind::SetIdInWritableDir),
+                ]
+            ),
+        ]
+    );
+    chmod(&safe, 0o755);
+}
+
+#[test]
+fn test_get_shadowed_executables() {
+    let path = [dir("a"), dir("b"), dir("c"), dir("d")].join(OsStr::new(":"));
+    assert_eq!(
+        get_shadowed(&path, CommandFilter::default()).0,
+        vec![
+            (
+                dir("c"),
+                vec![Shadow::new(
+                    dir("b"),
+                    "x".into(),
+                    ShadowKind::IdenticalContent
+                )]
+            ),
+            (
+                dir("d"),
+                vec![Shadow::new(dir("b"), "x".into(), ShadowKind::Symlink)]
+            ),
+        ]
+    );
+    assert_eq!(
+        get_shadowed(
+            &path,
+            CommandFilter {
+                no_symlinks: true,
+                ..CommandFilter::default()
+            }
+        )
+        .0,
+        vec![(
+            dir("c"),
+            vec![Shadow::new(
+                dir("b"),
+                "x".into(),
+                ShadowKind::IdenticalContent
+            )]
+        )]
+    );
+}
+
+#[test]
+fn test_classify_shadow() {
+    let first = temp_dir("classify-first");
+    let second = temp_dir("classify-second");
+    let file = |dir: &OsStr, name: &str| Path::new(dir).join(name);
+    fs::write(file(&first, "same"), "same").unwrap();
+    fs::hard_link(file(&first, "same"), file(&second, "same")).unwrap();
+    fs::write(file(&first, "link"), "link").unwrap();
+    std::os::unix::fs::symlink(file(&first, "link"), file(&second, "link")).unwrap();
+    fs::write(file(&first, "copy"), "copy").unwrap();
+    fs::write(file(&second, "copy"), "copy").unwrap();
+    fs::write(file(&first, "other"), "other").unwrap();
+    fs::write(file(&second, "other"), "OTHER").unwrap();
+
+    let classify = |name: &str| classify_shadow(&file(&second, name), &file(&first, name));
+    assert_eq!(classify("same"), ShadowKind::SameFile);
+    assert_eq!(classify("link"), ShadowKind::Symlink);
+    assert_eq!(classify("copy"), ShadowKind::IdenticalContent);
+    assert_eq!(classify("other"), ShadowKind::Different);
+}
+
+#[test]
+fn test_shadow_groups() {
+    let path = [dir("a"), dir("b"), dir("c")].join(OsStr::new(":"));
+    let mut analysis = Analysis::new(&path, &[Section::Shadows], ALL_FILES).unwrap();
+    analysis.shadows[1].1[1].kind = ShadowKind::Different;
+
+    analysis.set_benign(BenignShadows::Group);
+    assert_eq!(
+        analysis.shadow_groups(),
+        vec![
+            (
+                "Shadowed Files",
+                vec![(
+                    dir("c"),
+                    vec![Shadow::new(dir("b"), "x".into(), ShadowKind::Different)]
+                )]
+            ),
+            (
+                "Benign Shadowed Files",
+                vec![
+                    (
+                        dir("b"),
+                        vec![Shadow::new(
+                            dir("a"),
+                            "keepme.txt".into(),
+                            ShadowKind::IdenticalContent
+                        )]
+                    ),
+                    (
+                        dir("c"),
+                        vec![Shadow::new(
+                            dir("a"),
+                            "keepme.txt".into(),
+                            ShadowKind::IdenticalContent
+                        )]
+                    ),
+                ]
+            ),
+        ]
+    );
+
+    analysis.set_benign(BenignShadows::Hide);
+    assert_eq!(
+        analysis.shadow_groups(),
+        vec![(
+            "Shadowed Files",
+            vec![(
+                dir("c"),
+                vec![Shadow::new(dir("b"), "x".into(), ShadowKind::Different)]
+            )]
+        )]
+    );
+}
+
+#[test]
+fn test_get_broken_symlinks() {
+    let path = [dir("a"), dir("d"), dir("z")].join(OsStr::new(":"));
+    assert_eq!(
+        get_broken_symlinks(&path),
+        vec![(
+            dir("d"),
+            vec![
+                BrokenSymlink::new("gone".into(), vec![dir("d/../b/missing").into()], false),
+                BrokenSymlink::new(
+                    "hop".into(),
+                    vec![dir("d/gone").into(), dir("d/../b/missing").into()],
+                    false
+                ),
+                BrokenSymlink::new("loop".into(), vec![dir("d/loop").into()], true),
+            ]
+        )]
+    );
+    assert_eq!(
+        get_broken_symlinks(&dir("e")),
+        vec![(
+            dir("e"),
+            vec![BrokenSymlink::new(
+                "loop".into(),
+                vec![dir("e/../e/loop").into()],
+                true
+            )]
+        )]
+    );
+    assert_eq!(
+        get_invalid_reason(&dir("e/loop")),
+        Some(InvalidReason::SymlinkLoop)
+    );
+
+    // A chain longer than the kernel follows is reported as a loop.
+    let long = temp_dir("long-chain");
+    for i in 0..=SYMLOOP_MAX {
+        std::os::unix::fs::symlink(
+            format!("l{}", i + 1),
+            Path::new(&long).join(format!("l{i}")),
+        )
+        .unwrap();
+    }
+    let (chain, looped) = symlink_chain(&Path::new(&long).join("l0"));
+    assert_eq!((chain.len(), looped), (SYMLOOP_MAX, true));
+    assert_eq!(
+        BrokenSymlink::new("hop".into(), vec!["gone".into(), "missing".into()], false).describe(),
+        "hop  ->  gone  ->  missing (missing)"
+    );
+}
+
+#[test]
+fn test_clean_path() {
+    assert_eq!(clean_path(OsStr::new("/usr/local/bin/")), "/usr/local/bin");
+    assert_eq!(clean_path(OsStr::new("/usr//bin")), "/usr/bin");
+    assert_eq!(clean_path(OsStr::new("/usr/./bin/.")), "/usr/bin");
+    assert_eq!(clean_path(OsStr::new("/usr/local/../bin")), "/usr/bin");
+    assert_eq!(clean_path(OsStr::new("/../bin")), "/bin");
+    assert_eq!(clean_path(OsStr::new("/")), "/");
+    assert_eq!(clean_path(OsStr::new("//")), "/");
+    assert_eq!(clean_path(OsStr::new(".")), ".");
+    assert_eq!(clean_path(OsStr::new("./")), ".");
+    assert_eq!(clean_path(OsStr::new("bin/..")), ".");
+    assert_eq!(clean_path(OsStr::new("../../bin")), "../../bin");
+    assert_eq!(clean_path(OsStr::new("a/../../bin/")), "../bin");
+}
+
+#[test]
+fn test_get_alias_groups() {
+    let path = [
+        dir("a"),
+        dir("b/"),
+        dir("z/"),
+        dir("la"),
+        dir("b"),
+        dir("c"),
+        format!("{}//z", TEST_ROOT).into(),
+        dir("laa"),
+    ]
+    .join(OsStr::new(":"));
+    assert_eq!(
+        get_alias_groups(&path),
+        vec![
+            AliasGroup::new(
+                normal_dir("a"),
+                AliasKind::Symlink,
+                vec![dir("a"), dir("la"), dir("laa")]
+            ),
+            AliasGroup::new(
+                normal_dir("b"),
+                AliasKind::Lexical,
+                vec![dir("b/"), dir("b")]
+            ),
+            AliasGroup::new(
+                dir("z"),
+                AliasKind::Lexical,
+                vec![dir("z/"), format!("{}//z", TEST_ROOT).into()]
+            ),
+        ]
+    );
+}